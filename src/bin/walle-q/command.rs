@@ -1,4 +1,8 @@
-use clap::Parser;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+use walle_q::database::{self, Backend};
 
 use crate::config::{LogLevel, MetaConfig};
 
@@ -19,6 +23,70 @@ pub struct Comm {
 
     #[clap(long, help = "json config")]
     pub json_config: Option<String>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    #[clap(subcommand, about = "manage stored data, stop running walle-q first")]
+    Db(DbCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    #[clap(about = "export messages, images and voices to a JSON Lines file")]
+    Export {
+        #[clap(long, help = "backend to export")]
+        backend: Backend,
+        #[clap(help = "output file")]
+        output: PathBuf,
+    },
+    #[clap(about = "import a JSON Lines file exported by `db export`")]
+    Import {
+        #[clap(long, help = "backend to import into")]
+        backend: Backend,
+        #[clap(help = "input file")]
+        input: PathBuf,
+    },
+    #[clap(about = "copy all records from one backend to another")]
+    Migrate {
+        #[clap(long, help = "backend to read")]
+        from: Backend,
+        #[clap(long, help = "backend to write")]
+        to: Backend,
+    },
+    #[clap(about = "compare records of the configured backends")]
+    Check {
+        #[clap(long, help = "copy missing records from the backends having them")]
+        fix: bool,
+    },
+}
+
+impl DbCommand {
+    pub fn run(self, meta: &MetaConfig, data_path: &Arc<String>) {
+        let result = match self {
+            Self::Export { backend, output } => database::export(data_path, backend, &output),
+            Self::Import { backend, input } => database::import(data_path, backend, &input),
+            Self::Migrate { from, to } => database::migrate(data_path, from, to),
+            Self::Check { fix } => {
+                database::check(data_path, &meta.backends(), fix).map(|reports| {
+                    for report in &reports {
+                        println!("{report}");
+                    }
+                    reports.iter().map(|report| report.fixed).sum()
+                })
+            }
+        };
+        match result {
+            Ok(count) => println!("done, {count} records"),
+            Err(e) => {
+                println!("failed: {}", e.message);
+                std::process::exit(1)
+            }
+        }
+    }
 }
 
 impl Comm {
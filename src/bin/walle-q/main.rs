@@ -1,16 +1,16 @@
 use clap::Parser;
 use std::sync::Arc;
 use walle_core::obc::ImplOBC;
-use walle_q::{config, init, multi, WALLE_Q};
+use walle_q::{config, database, init, multi, WALLE_Q};
 
 mod command;
 
 #[tokio::main]
 async fn main() {
-    let comm = command::Comm::parse();
+    let mut comm = command::Comm::parse();
+    let command = comm.command.take();
     let config = comm.config();
     config.meta.subscribe();
-    init(config.meta.data_path.clone(), config.meta.log_path.clone()).await;
 
     let data_path = std::sync::Arc::new(
         config
@@ -19,16 +19,47 @@ async fn main() {
             .clone()
             .unwrap_or(walle_q::DATA_PATH.to_owned()),
     );
+    if let Some(command::Command::Db(c)) = command {
+        return c.run(&config.meta, &data_path);
+    }
+    init(config.meta.data_path.clone(), config.meta.log_path.clone()).await;
+
+    let database = config.meta.db(&data_path);
+    database::retention::spawn_sweeper(
+        database.clone(),
+        data_path.clone(),
+        config.meta.retention.clone(),
+    );
     let ah = multi::MultiAH::new(
         config.meta.super_token.clone(),
         config.meta.event_cache_size,
-        config.meta.db(&data_path),
+        database.clone(),
         data_path,
+        config.meta.fetch.clone(),
     );
     let ob = Arc::new(walle_core::OneBot::new(
         ah,
         ImplOBC::new(WALLE_Q.to_owned()),
     ));
     ob.start(config.qq, config.onebot, false).await.unwrap();
-    ob.wait_all().await;
+    tokio::select! {
+        _ = ob.wait_all() => {}
+        _ = shutdown_signal() => tracing::info!(target: WALLE_Q, "shutting down"),
+    }
+    database.flush().await.ok();
+}
+
+/// ctrl-c, or SIGTERM on unix, so buffered database writes are flushed before exiting
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut term = signal(SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
 }
@@ -11,7 +11,7 @@ use tracing::metadata::LevelFilter;
 use tracing::{info, warn};
 use walle_core::config::ImplConfig;
 
-use crate::database::WQDatabase;
+use crate::database::{Backend, Database, Storage, WQDatabase};
 use crate::WALLE_Q;
 
 type IOResult<T> = Result<T, std::io::Error>;
@@ -152,6 +152,21 @@ pub struct MetaConfig {
     pub event_cache_size: usize,
     pub sled: bool,
     pub leveldb: bool,
+    #[serde(default)]
+    pub sqlite: bool,
+    /// in-memory database, read before the on-disk ones
+    #[serde(default)]
+    pub memory: bool,
+    /// size cap (MB) of the in-memory database, unlimited if not set
+    #[serde(default)]
+    pub memory_max_mb: Option<u64>,
+    /// back-fill records missing from earlier backends when a later backend has them
+    #[serde(default)]
+    pub read_repair: bool,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub fetch: FetchConfig,
     pub data_path: Option<String>,
     pub log_path: Option<String>,
     pub super_token: Option<String>,
@@ -164,6 +179,12 @@ impl Default for MetaConfig {
             event_cache_size: 10,
             sled: false,
             leveldb: true,
+            sqlite: false,
+            memory: false,
+            memory_max_mb: None,
+            read_repair: false,
+            retention: RetentionConfig::default(),
+            fetch: FetchConfig::default(),
             data_path: None,
             log_path: None,
             super_token: None,
@@ -171,6 +192,72 @@ impl Default for MetaConfig {
     }
 }
 
+/// retention of stored messages and media files, nothing is pruned by default
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// days to keep messages and image / voice / video / uploaded files
+    pub max_age_days: Option<u64>,
+    /// max total size (MB) of image, voice, video and uploaded files, oldest files are removed
+    /// first
+    pub max_media_size_mb: Option<u64>,
+    /// seconds between two sweeps
+    pub interval: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            max_media_size_mb: None,
+            interval: 3600,
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn enabled(&self) -> bool {
+        self.max_age_days.is_some() || self.max_media_size_mb.is_some()
+    }
+}
+
+/// policy for urls and paths supplied by callers of `upload_file` and image segments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FetchConfig {
+    /// allowed url schemes, `file` urls are checked like paths
+    pub schemes: Vec<String>,
+    /// hosts always allowed, subdomains included, only these are allowed if not empty
+    pub allow_hosts: Vec<String>,
+    /// hosts never allowed, subdomains included
+    pub deny_hosts: Vec<String>,
+    /// reject hosts resolving to loopback, private or link-local addresses unless allowed,
+    /// checked on every connection including redirects
+    pub block_private: bool,
+    /// max size (MB) of one download, unlimited if not set, http bodies are cut off once over
+    pub max_download_mb: Option<u64>,
+    /// seconds before a download is given up
+    pub timeout: u64,
+    /// directory `path` uploads must stay within, any path if not set
+    pub path_root: Option<String>,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            schemes: ["http", "https", "file", "base64"]
+                .map(ToOwned::to_owned)
+                .to_vec(),
+            allow_hosts: vec![],
+            deny_hosts: vec![],
+            block_private: true,
+            max_download_mb: None,
+            timeout: 60,
+            path_root: None,
+        }
+    }
+}
+
 impl From<LogLevel> for LevelFilter {
     fn from(log: LogLevel) -> Self {
         match log {
@@ -229,14 +316,29 @@ impl MetaConfig {
             .init();
     }
 
+    /// enabled backends in read order
+    pub fn backends(&self) -> Vec<Backend> {
+        [
+            (self.sled, Backend::Sled),
+            (self.leveldb, Backend::Leveldb),
+            (self.sqlite, Backend::Sqlite),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, backend)| enabled.then_some(backend))
+        .collect()
+    }
+
     pub fn db(&self, base_path: &Arc<String>) -> Arc<WQDatabase> {
-        let mut db = WQDatabase::new(base_path);
-        if self.sled {
-            db = db.add_sled();
+        let mut db = Storage::new(base_path).read_repair(self.read_repair);
+        if self.memory {
+            db = db.add_memory(self.memory_max_mb.map(|mb| (mb * 1024 * 1024) as usize));
+        }
+        for backend in self.backends() {
+            db = db.add(backend);
         }
-        if self.leveldb {
-            db = db.add_level()
+        if let Err(e) = db.migrate_message_ids() {
+            warn!(target: WALLE_Q, "migrate stored messages failed: {}", e.message);
         }
-        Arc::new(db)
+        Arc::new(WQDatabase::spawn(db))
     }
 }
@@ -0,0 +1,313 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use walle_core::{
+    event::Event,
+    resp::{resp_error, RespError},
+};
+
+use super::{
+    Backend, DataBaseEvent, Database, Images, MessageId, SImage, SVoice, Storage, StoredFile,
+    Videos, Voices,
+};
+use crate::error;
+
+/// one line of a JSON Lines dump
+///
+/// image and voice records keep the bytes stored by the backends, base64 encoded:
+/// MessagePack `Images` for images, `SVoice::to_data` for voices and MessagePack `Videos` for videos,
+/// file records are written as is, the content stays in `FILE_DIR`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DumpRecord {
+    Message {
+        event: Event,
+        seqs: Vec<i32>,
+        rands: Vec<i32>,
+    },
+    Image {
+        data: String,
+    },
+    Voice {
+        data: String,
+    },
+    Video {
+        data: String,
+    },
+    File(StoredFile),
+}
+
+impl DumpRecord {
+    pub(crate) fn message(value: DataBaseEvent) -> Self {
+        Self::Message {
+            event: value.event,
+            seqs: value.seqs,
+            rands: value.rands,
+        }
+    }
+
+    pub(crate) fn image(data: &[u8]) -> Self {
+        Self::Image {
+            data: STANDARD.encode(data),
+        }
+    }
+
+    pub(crate) fn voice(data: &[u8]) -> Self {
+        Self::Voice {
+            data: STANDARD.encode(data),
+        }
+    }
+
+    pub(crate) fn video(data: &[u8]) -> Self {
+        Self::Video {
+            data: STANDARD.encode(data),
+        }
+    }
+
+    /// identity of the record across backends, `None` if the record can not be decoded
+    pub(crate) fn key(&self) -> Option<String> {
+        match self {
+            Self::Message { event, .. } => Some(format!("message:{}", event.message_id())),
+            Self::Image { data } => {
+                let data = STANDARD.decode(data).ok()?;
+                let image: Images = rmp_serde::from_slice(&data).ok()?;
+                Some(format!("image:{}", hex::encode(image.image_id())))
+            }
+            Self::Voice { data } => {
+                let voice = Voices::from_data(&STANDARD.decode(data).ok()?)?;
+                Some(format!("voice:{}", hex::encode(voice.voice_id())))
+            }
+            Self::Video { data } => {
+                let data = STANDARD.decode(data).ok()?;
+                let video: Videos = rmp_serde::from_slice(&data).ok()?;
+                Some(format!("video:{}", video.hex_video_id()))
+            }
+            Self::File(file) => Some(format!("file:{}", file.sha256)),
+        }
+    }
+
+    pub(crate) fn insert_into(self, db: &impl Database) -> Result<(), RespError> {
+        match self {
+            Self::Message { event, seqs, rands } => db.insert_message(&event, seqs, rands),
+            Self::Image { data } => {
+                let data = STANDARD.decode(data).map_err(resp_error::database_error)?;
+                let image: Images =
+                    rmp_serde::from_slice(&data).map_err(resp_error::database_error)?;
+                db.insert_image(&image)
+            }
+            Self::Voice { data } => {
+                let data = STANDARD.decode(data).map_err(resp_error::database_error)?;
+                let voice = Voices::from_data(&data)
+                    .ok_or_else(|| resp_error::database_error("bad voice record"))?;
+                db.insert_voice(&voice)
+            }
+            Self::Video { data } => {
+                let data = STANDARD.decode(data).map_err(resp_error::database_error)?;
+                let video: Videos =
+                    rmp_serde::from_slice(&data).map_err(resp_error::database_error)?;
+                db.insert_video(&video)
+            }
+            Self::File(file) => db.insert_file(&file),
+        }
+    }
+}
+
+/// write every record of `backend` to `output`, returns the record count
+///
+/// backends lock their files, walle-q must be stopped first
+pub fn export(
+    data_path: &Arc<String>,
+    backend: Backend,
+    output: &Path,
+) -> Result<usize, RespError> {
+    let storage = Storage::new(data_path).add(backend);
+    let mut writer = BufWriter::new(File::create(output).map_err(error::file_create_error)?);
+    let mut count = 0;
+    storage.for_each_record(&mut |record| {
+        serde_json::to_writer(&mut writer, &record).map_err(error::file_write_error)?;
+        writer.write_all(b"\n").map_err(error::file_write_error)?;
+        count += 1;
+        Ok(())
+    })?;
+    writer.flush().map_err(error::file_write_error)?;
+    Ok(count)
+}
+
+/// insert every record of `input` into `backend`, index entries are rebuilt
+pub fn import(data_path: &Arc<String>, backend: Backend, input: &Path) -> Result<usize, RespError> {
+    let storage = Storage::new(data_path).add(backend);
+    let reader = BufReader::new(File::open(input).map_err(error::file_open_error)?);
+    let mut count = 0;
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(error::file_read_error)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: DumpRecord = serde_json::from_str(&line)
+            .map_err(|e| error::bad_param(format!("line {}: {}", number + 1, e)))?;
+        record.insert_into(&storage)?;
+        count += 1;
+    }
+    storage.flush()?;
+    Ok(count)
+}
+
+/// copy every record of `from` into `to`, the source is left untouched
+pub fn migrate(data_path: &Arc<String>, from: Backend, to: Backend) -> Result<usize, RespError> {
+    if from == to {
+        return Err(error::bad_param("source and target backend are the same"));
+    }
+    let source = Storage::new(data_path).add(from);
+    let target = Storage::new(data_path).add(to);
+    let mut count = 0;
+    source.for_each_record(&mut |record| {
+        record.insert_into(&target)?;
+        count += 1;
+        Ok(())
+    })?;
+    target.flush()?;
+    Ok(count)
+}
+
+/// result of `check` for one backend
+#[derive(Debug)]
+pub struct BackendCheck {
+    pub backend: Backend,
+    pub records: usize,
+    /// records other backends have but this one lacks
+    pub missing: usize,
+    /// records this backend has with content different from the preferred copy
+    pub differing: usize,
+    pub fixed: usize,
+}
+
+impl Display for BackendCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?}: {} records, {} missing, {} differing, {} fixed",
+            self.backend, self.records, self.missing, self.differing, self.fixed
+        )
+    }
+}
+
+/// content of a record comparable across backends, maps compare regardless of key order
+fn digest(record: &DumpRecord) -> Vec<u8> {
+    fn canonical(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<_> = map.into_iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                serde_json::Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| (k, canonical(v)))
+                        .collect(),
+                )
+            }
+            serde_json::Value::Array(values) => {
+                serde_json::Value::Array(values.into_iter().map(canonical).collect())
+            }
+            value => value,
+        }
+    }
+    let content = serde_json::to_value(record)
+        .map(|value| canonical(value).to_string())
+        .unwrap_or_default();
+    sha2::Sha256::digest(content.as_bytes()).to_vec()
+}
+
+/// a recalled copy of a message is newer than one without the mark
+fn is_recalled(record: &DumpRecord) -> bool {
+    matches!(record, DumpRecord::Message { event, .. } if event.extra.contains_key("recall"))
+}
+
+/// compare the records of `backends` by key and content, with `fix` copy missing and
+/// differing records from the preferred copy
+///
+/// the preferred copy of a record is the first recalled one, or the one of the first
+/// backend having it
+pub fn check(
+    data_path: &Arc<String>,
+    backends: &[Backend],
+    fix: bool,
+) -> Result<Vec<BackendCheck>, RespError> {
+    if backends.len() < 2 {
+        return Err(error::bad_param("at least two backends are needed"));
+    }
+    let storages: Vec<Storage> = backends
+        .iter()
+        .map(|backend| Storage::new(data_path).add(*backend))
+        .collect();
+    let mut contents: Vec<HashMap<String, (Vec<u8>, bool)>> = vec![];
+    for storage in &storages {
+        let mut records = HashMap::new();
+        storage.for_each_record(&mut |record| {
+            if let Some(key) = record.key() {
+                records.insert(key, (digest(&record), is_recalled(&record)));
+            }
+            Ok(())
+        })?;
+        contents.push(records);
+    }
+    // key -> backend holding the preferred copy
+    let mut preferred: HashMap<&String, usize> = HashMap::new();
+    for (i, records) in contents.iter().enumerate() {
+        for (key, (_, recalled)) in records {
+            let replace = match preferred.get(key) {
+                Some(&j) => *recalled && !contents[j][key].1,
+                None => true,
+            };
+            if replace {
+                preferred.insert(key, i);
+            }
+        }
+    }
+    let mut reports = vec![];
+    for (i, target) in storages.iter().enumerate() {
+        let mut report = BackendCheck {
+            backend: backends[i],
+            records: contents[i].len(),
+            missing: 0,
+            differing: 0,
+            fixed: 0,
+        };
+        // stale keys of this backend grouped by the backend to copy them from
+        let mut stale: Vec<HashSet<String>> = vec![HashSet::new(); storages.len()];
+        for (key, &j) in &preferred {
+            if j == i {
+                continue;
+            }
+            match contents[i].get(*key) {
+                None => report.missing += 1,
+                Some((digest, _)) if digest != &contents[j][*key].0 => report.differing += 1,
+                Some(_) => continue,
+            }
+            stale[j].insert((*key).clone());
+        }
+        if fix && (report.missing > 0 || report.differing > 0) {
+            for (j, source) in storages.iter().enumerate() {
+                if stale[j].is_empty() {
+                    continue;
+                }
+                source.for_each_record(&mut |record| {
+                    if record.key().map_or(false, |key| stale[j].remove(&key)) {
+                        record.insert_into(target)?;
+                        report.fixed += 1;
+                    }
+                    Ok(())
+                })?;
+            }
+            target.flush()?;
+        }
+        reports.push(report);
+    }
+    Ok(reports)
+}
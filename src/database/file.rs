@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use walle_core::resp::RespError;
+use walle_core::structs::FileId;
+
+use crate::error;
+
+pub async fn save_file(
+    data: &[u8],
+    name: &str,
+    mime: Option<String>,
+    base_path: &str,
+) -> Result<StoredFile, RespError> {
+    let sha256 = hex::encode(sha2::Sha256::digest(data));
+    let file = StoredFile::new(sha256, name, mime, data.len() as u64);
+    tokio::fs::write(file.path(base_path), data)
+        .await
+        .map_err(error::file_write_error)?;
+    Ok(file)
+}
+
+/// generic attachment in `FILE_DIR`, content addressed by sha256
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredFile {
+    /// hex sha256 of the content, also used as file_id
+    pub sha256: String,
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+}
+
+impl StoredFile {
+    /// `mime` is guessed from the extension of `name` if not given
+    pub fn new(sha256: String, name: &str, mime: Option<String>, size: u64) -> Self {
+        Self {
+            sha256,
+            name: name.to_owned(),
+            mime: mime.unwrap_or_else(|| {
+                mime_guess::from_path(name)
+                    .first_or_octet_stream()
+                    .to_string()
+            }),
+            size,
+        }
+    }
+
+    pub fn file_id(&self) -> Vec<u8> {
+        hex::decode(&self.sha256).unwrap_or_default()
+    }
+
+    pub fn path(&self, base_path: &str) -> PathBuf {
+        let mut path = PathBuf::from(format!("{}/{}", base_path, crate::FILE_DIR));
+        path.push(&self.sha256);
+        path
+    }
+
+    pub fn as_file_id_content(&self) -> FileId {
+        FileId {
+            file_id: self.sha256.clone(),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashSet;
+
+use walle_core::{event::Event, util::ValueMapExt};
+
+use super::{MessageId, StoredMessageId};
+
+const MAX_TIME: i64 = 999_999_999_999;
+
+/// key of the conversation history index, ordered by conversation then time
+pub(crate) fn history_index_key(conversation_key: &str, time: i64, message_id: &str) -> String {
+    format!(
+        "history\0{}\0{:012}\0{}",
+        conversation_key, time, message_id
+    )
+}
+
+/// key of the inverted index over `alt_message`, ordered by term then time
+pub(crate) fn term_index_key(term: &str, time: i64, message_id: &str) -> String {
+    format!("term\0{}\0{:012}\0{}", term, time, message_id)
+}
+
+pub(crate) const MEDIA_IMAGE: &str = "image";
+pub(crate) const MEDIA_VOICE: &str = "voice";
+pub(crate) const MEDIA_VIDEO: &str = "video";
+
+/// key of the media age index, ordered by the time a media record was stored. the entry
+/// maps to the key itself so a scan yields the keys
+pub(crate) fn media_index_key(time: i64, kind: &str, id: &str) -> String {
+    format!("media\0{:012}\0{}\0{}", time, kind, id)
+}
+
+/// time, kind and hex id of a `media_index_key`
+pub(crate) fn parse_media_index_key(key: &str) -> Option<(i64, &str, &str)> {
+    let mut parts = key.strip_prefix("media\0")?.splitn(3, '\0');
+    Some((parts.next()?.parse().ok()?, parts.next()?, parts.next()?))
+}
+
+/// key holding the time a media record was last stored, `media_index_key`s of the record
+/// with an earlier time are stale
+pub(crate) fn media_time_key(kind: &str, id: &str) -> String {
+    format!("media_time\0{}\0{}", kind, id)
+}
+
+/// index keys share the key space with messages in leveldb
+pub(crate) fn is_index_key(key: &[u8]) -> bool {
+    key.starts_with(b"history\0")
+        || key.starts_with(b"term\0")
+        || key.starts_with(b"media\0")
+        || key.starts_with(b"media_time\0")
+}
+
+/// all index keys of a stored message, every key maps to the message_id
+///
+/// messages without a `StoredMessageId` are not indexed
+pub(crate) fn index_keys(event: &Event) -> Vec<String> {
+    let message_id = event.message_id();
+    let Some(id) = StoredMessageId::parse(&message_id) else {
+        return vec![];
+    };
+    let time = event.time as i64;
+    let mut keys = vec![history_index_key(&id.conversation_key(), time, &message_id)];
+    let alt_message: String = event.extra.get_downcast("alt_message").unwrap_or_default();
+    for term in index_terms(&alt_message) {
+        keys.push(term_index_key(&term, time, &message_id));
+    }
+    keys
+}
+
+/// split text into terms, ascii words are lowercased, other words are split into chars
+/// and char bigrams so chinese text can be searched without a dictionary
+fn terms(text: &str, unigram: bool) -> Vec<String> {
+    fn flush_word(word: &mut String, terms: &mut Vec<String>) {
+        if !word.is_empty() {
+            terms.push(word.to_lowercase());
+            word.clear();
+        }
+    }
+    fn flush_chars(chars: &mut Vec<char>, unigram: bool, terms: &mut Vec<String>) {
+        if chars.len() == 1 || unigram {
+            terms.extend(chars.iter().map(ToString::to_string));
+        }
+        terms.extend(chars.windows(2).map(|w| w.iter().collect::<String>()));
+        chars.clear();
+    }
+    let mut terms = vec![];
+    let mut word = String::new();
+    let mut chars = vec![];
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            flush_chars(&mut chars, unigram, &mut terms);
+            word.push(c);
+        } else if c.is_alphanumeric() {
+            flush_word(&mut word, &mut terms);
+            chars.push(c);
+        } else {
+            flush_word(&mut word, &mut terms);
+            flush_chars(&mut chars, unigram, &mut terms);
+        }
+    }
+    flush_word(&mut word, &mut terms);
+    flush_chars(&mut chars, unigram, &mut terms);
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+pub(crate) fn index_terms(text: &str) -> Vec<String> {
+    terms(text, true)
+}
+
+pub(crate) fn query_terms(text: &str) -> Vec<String> {
+    terms(text, false)
+}
+
+/// range and filters of a conversation history query
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    pub conversation_key: String,
+    pub start_time: Option<f64>,
+    pub end_time: Option<f64>,
+    pub start_seq: Option<i32>,
+    pub end_seq: Option<i32>,
+    pub limit: usize,
+}
+
+impl HistoryQuery {
+    /// `[start, end)` of history index keys covered by the time range
+    pub fn index_range(&self) -> (String, String) {
+        (
+            history_index_key(
+                &self.conversation_key,
+                self.start_time.map(|t| t as i64).unwrap_or(0),
+                "",
+            ),
+            history_index_key(
+                &self.conversation_key,
+                self.end_time.map(|t| t as i64 + 1).unwrap_or(MAX_TIME),
+                "",
+            ),
+        )
+    }
+
+    pub fn seq_match(&self, seq: i32) -> bool {
+        self.start_seq.map_or(true, |s| seq >= s) && self.end_seq.map_or(true, |e| seq <= e)
+    }
+
+    /// keep the latest `limit` message_ids matching the seq range, ids must be in time order
+    pub fn select(&self, ids: Vec<String>) -> Vec<String> {
+        let mut ids = ids
+            .into_iter()
+            .filter(|id| {
+                StoredMessageId::parse(id)
+                    .map(|id| self.seq_match(id.seq))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        ids.drain(..ids.len().saturating_sub(self.limit));
+        ids
+    }
+}
+
+/// keyword search over `alt_message` of one bot's stored messages
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub bot: String,
+    pub keyword: String,
+    pub group_id: Option<String>,
+    pub user_id: Option<String>,
+    pub start_time: Option<f64>,
+    pub end_time: Option<f64>,
+    pub limit: usize,
+}
+
+impl SearchQuery {
+    /// `[start, end)` of term index keys covered by the time range
+    pub fn term_range(&self, term: &str) -> (String, String) {
+        (
+            term_index_key(term, self.start_time.map(|t| t as i64).unwrap_or(0), ""),
+            term_index_key(
+                term,
+                self.end_time.map(|t| t as i64 + 1).unwrap_or(MAX_TIME),
+                "",
+            ),
+        )
+    }
+
+    /// message_ids containing every term of the keyword, in time order
+    pub fn intersect(&self, postings: Vec<Vec<String>>) -> Vec<String> {
+        let mut postings = postings.into_iter();
+        let Some(first) = postings.next() else {
+            return vec![];
+        };
+        let rest = postings
+            .map(|ids| ids.into_iter().collect::<HashSet<_>>())
+            .collect::<Vec<_>>();
+        first
+            .into_iter()
+            .filter(|id| rest.iter().all(|ids| ids.contains(id)))
+            .collect()
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        let get = |key: &str| event.extra.get_downcast::<String>(key).ok();
+        event.message_id().starts_with(&format!("{}:", self.bot))
+            && self
+                .group_id
+                .as_ref()
+                .map_or(true, |g| get("group_id").as_ref() == Some(g))
+            && self
+                .user_id
+                .as_ref()
+                .map_or(true, |u| get("user_id").as_ref() == Some(u))
+            && get("alt_message")
+                .unwrap_or_default()
+                .to_lowercase()
+                .contains(&self.keyword.to_lowercase())
+    }
+}
+
+#[test]
+fn test_terms() {
+    assert_eq!(
+        index_terms("Check https://Example.com 链接"),
+        vec!["check", "com", "example", "https", "接", "链", "链接"]
+    );
+    assert_eq!(query_terms("链接地址"), vec!["地址", "接地", "链接"]);
+    assert_eq!(query_terms("链"), vec!["链"]);
+}
+
+#[test]
+fn test_media_index_key() {
+    let key = media_index_key(1700000000, MEDIA_IMAGE, "ab01");
+    assert_eq!(
+        parse_media_index_key(&key),
+        Some((1700000000, MEDIA_IMAGE, "ab01"))
+    );
+    assert!(is_index_key(key.as_bytes()));
+    assert_eq!(
+        parse_media_index_key(&media_time_key(MEDIA_IMAGE, "ab01")),
+        None
+    );
+}
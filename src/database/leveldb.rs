@@ -1,59 +1,86 @@
 use crate::error;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use walle_core::{prelude::Event, resp::RespError};
+use walle_core::{
+    prelude::Event,
+    resp::{resp_error, RespError},
+};
 
-use rusty_leveldb::{Options, DB};
+use rusty_leveldb::{LdbIterator, Options, WriteBatch, DB};
 
-use super::{DataBaseEvent, DataBaseEventRef, Database, DatabaseInit, MessageId, SImage, SVoice};
+use super::{
+    index, migrate_legacy_message, DataBaseEvent, DataBaseEventRef, Database, DatabaseInit,
+    DumpRecord, Images, MessageId, SImage, SVoice, StoredFile, Videos, Voices,
+};
 
-const MEM_CACHE_LIMIT: usize = 10;
+/// file and video records share the key space with messages, images and voices,
+/// keep them apart by prefix
+const FILE_PREFIX: &[u8] = b"file\0";
+const VIDEO_PREFIX: &[u8] = b"video\0";
+/// bookkeeping keys, not records
+const META_PREFIX: &[u8] = b"meta\0";
+/// written once legacy message ids are migrated, later startups skip the scan
+const MIGRATED_KEY: &[u8] = b"meta\0migrated_message_ids";
 
-pub(crate) struct LevelDb(std::sync::Mutex<DB>, AtomicUsize);
+fn file_key(key: &[u8]) -> Vec<u8> {
+    [FILE_PREFIX, key].concat()
+}
 
-impl DatabaseInit for LevelDb {
-    fn init(base_path: &str) -> Self {
-        Self(
-            std::sync::Mutex::new(
-                DB::open(format!("{}/{}", base_path, "leveldb"), Options::default()).unwrap(),
-            ),
-            AtomicUsize::new(0),
-        )
+fn video_key(key: &[u8]) -> Vec<u8> {
+    [VIDEO_PREFIX, key].concat()
+}
+
+/// message record with its index entries, written in one batch
+fn put_message(
+    batch: &mut WriteBatch,
+    value: &Event,
+    seqs: Vec<i32>,
+    rands: Vec<i32>,
+) -> Result<(), RespError> {
+    let message_id = value.message_id();
+    let data = rmp_serde::to_vec(&DataBaseEventRef {
+        event: value,
+        seqs,
+        rands,
+    })
+    .map_err(resp_error::database_error)?;
+    batch.put(message_id.as_bytes(), &data);
+    for key in index::index_keys(value) {
+        batch.put(key.as_bytes(), message_id.as_bytes());
     }
+    Ok(())
 }
 
-impl LevelDb {
-    fn flush(&self, mut db: std::sync::MutexGuard<DB>) {
-        if self.1.load(Ordering::Relaxed) > MEM_CACHE_LIMIT {
-            tracing::debug!(target: crate::WALLE_Q, "Flushing leveldb cache");
-            db.flush().unwrap();
-            self.1.store(0, Ordering::Relaxed);
-        } else {
-            self.1.fetch_add(1, Ordering::Relaxed);
-        }
+pub(crate) struct LevelDb(std::sync::Mutex<DB>);
+
+impl DatabaseInit for LevelDb {
+    fn init(base_path: &str) -> Self {
+        Self(std::sync::Mutex::new(
+            DB::open(format!("{}/{}", base_path, "leveldb"), Options::default()).unwrap(),
+        ))
     }
 }
 
 impl Database for LevelDb {
-    fn get_message(&self, key: &str) -> Option<DataBaseEvent> {
+    fn get_message(&self, key: &str) -> Result<Option<DataBaseEvent>, RespError> {
         self.0
             .lock()
             .unwrap()
             .get(key.as_bytes())
-            .and_then(|v| rmp_serde::from_slice(&v).unwrap())
+            .map(|v| rmp_serde::from_slice(&v).map_err(resp_error::database_error))
+            .transpose()
     }
-    fn insert_message(&self, value: &Event, seqs: Vec<i32>, rands: Vec<i32>) {
-        let mut db = self.0.lock().unwrap();
-        db.put(
-            value.message_id().as_bytes(),
-            &rmp_serde::to_vec(&DataBaseEventRef {
-                event: value,
-                seqs,
-                rands,
-            })
-            .unwrap(),
-        )
-        .unwrap();
-        self.flush(db);
+    fn insert_message(
+        &self,
+        value: &Event,
+        seqs: Vec<i32>,
+        rands: Vec<i32>,
+    ) -> Result<(), RespError> {
+        let mut batch = WriteBatch::new();
+        put_message(&mut batch, value, seqs, rands)?;
+        self.0
+            .lock()
+            .unwrap()
+            .write(batch, false)
+            .map_err(resp_error::database_error)
     }
     fn get_image<T>(&self, key: &[u8]) -> Result<Option<T>, RespError>
     where
@@ -66,14 +93,15 @@ impl Database for LevelDb {
             .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
             .transpose()
     }
-    fn insert_image<T>(&self, value: &T)
+    fn insert_image<T>(&self, value: &T) -> Result<(), RespError>
     where
         T: serde::Serialize + SImage,
     {
+        let data = rmp_serde::to_vec(value).map_err(resp_error::database_error)?;
         let mut db = self.0.lock().unwrap();
-        db.put(&value.image_id(), &rmp_serde::to_vec(value).unwrap())
-            .unwrap();
-        self.flush(db);
+        db.put(&value.image_id(), &data)
+            .map_err(resp_error::database_error)?;
+        Ok(())
     }
     fn get_voice<T: SVoice>(&self, key: &[u8]) -> Result<Option<T>, RespError> {
         self.0
@@ -83,9 +111,223 @@ impl Database for LevelDb {
             .map(|v| SVoice::from_data(&v).ok_or_else(|| error::file_type_not_match("")))
             .transpose()
     }
-    fn insert_voice<T: SVoice>(&self, value: &T) {
+    fn insert_voice<T: SVoice>(&self, value: &T) -> Result<(), RespError> {
         let mut db = self.0.lock().unwrap();
-        db.put(&value.voice_id(), &value.to_data()).unwrap();
-        self.flush(db);
+        db.put(&value.voice_id(), &value.to_data())
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn get_file(&self, key: &[u8]) -> Result<Option<StoredFile>, RespError> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&file_key(key))
+            .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
+            .transpose()
+    }
+    fn insert_file(&self, value: &StoredFile) -> Result<(), RespError> {
+        let data = rmp_serde::to_vec(value).map_err(resp_error::database_error)?;
+        let mut db = self.0.lock().unwrap();
+        db.put(&file_key(&value.file_id()), &data)
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn get_video(&self, key: &[u8]) -> Result<Option<Videos>, RespError> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&video_key(key))
+            .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
+            .transpose()
+    }
+    fn insert_video(&self, value: &Videos) -> Result<(), RespError> {
+        let data = rmp_serde::to_vec(value).map_err(resp_error::database_error)?;
+        let mut db = self.0.lock().unwrap();
+        db.put(&video_key(&value.video_id()), &data)
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn scan_index(&self, start: &str, end: &str) -> Result<Vec<String>, RespError> {
+        let mut db = self.0.lock().unwrap();
+        let mut iter = db.new_iter().map_err(resp_error::database_error)?;
+        let (mut key, mut value) = (vec![], vec![]);
+        let mut ids = vec![];
+        iter.seek(start.as_bytes());
+        while iter.valid() {
+            iter.current(&mut key, &mut value);
+            if key.as_slice() >= end.as_bytes() {
+                break;
+            }
+            ids.push(String::from_utf8_lossy(&value).into_owned());
+            iter.advance();
+        }
+        Ok(ids)
+    }
+    fn put_index(&self, key: &str, value: &str) -> Result<(), RespError> {
+        self.0
+            .lock()
+            .unwrap()
+            .put(key.as_bytes(), value.as_bytes())
+            .map_err(resp_error::database_error)
+    }
+    fn remove_index(&self, key: &str) -> Result<(), RespError> {
+        self.0
+            .lock()
+            .unwrap()
+            .delete(key.as_bytes())
+            .map_err(resp_error::database_error)
+    }
+    fn migrate_message_ids(&self) -> Result<usize, RespError> {
+        let mut db = self.0.lock().unwrap();
+        if db.get(MIGRATED_KEY).is_some() {
+            return Ok(0);
+        }
+        let mut migrated = vec![];
+        {
+            let mut iter = db.new_iter().map_err(resp_error::database_error)?;
+            let (mut key, mut value) = (vec![], vec![]);
+            while iter.advance() {
+                iter.current(&mut key, &mut value);
+                let Ok(old) = std::str::from_utf8(&key) else {
+                    continue;
+                };
+                let Ok(event) = rmp_serde::from_slice(&value) else {
+                    continue;
+                };
+                if let Some((_, event)) = migrate_legacy_message(old, event) {
+                    migrated.push((key.clone(), event));
+                }
+            }
+        }
+        let count = migrated.len();
+        // the new record and the removal of the old key land together, a failure loses nothing
+        for (old, event) in migrated {
+            let mut batch = WriteBatch::new();
+            put_message(&mut batch, &event.event, event.seqs, event.rands)?;
+            batch.delete(&old);
+            db.write(batch, false).map_err(resp_error::database_error)?;
+        }
+        db.put(MIGRATED_KEY, b"1")
+            .map_err(resp_error::database_error)?;
+        db.flush().map_err(resp_error::database_error)?;
+        Ok(count)
+    }
+    fn remove_messages_before(
+        &self,
+        time: f64,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<(usize, Option<Vec<u8>>), RespError> {
+        let mut db = self.0.lock().unwrap();
+        let mut removed = vec![];
+        let mut next = None;
+        {
+            let mut iter = db.new_iter().map_err(resp_error::database_error)?;
+            match after {
+                Some(after) => iter.seek(after),
+                None => {
+                    iter.advance();
+                }
+            }
+            let (mut key, mut value) = (vec![], vec![]);
+            let mut scanned = 0;
+            while iter.current(&mut key, &mut value) {
+                // seeking lands on `after` itself if it still exists
+                if Some(key.as_slice()) != after {
+                    scanned += 1;
+                    if !index::is_index_key(&key) {
+                        if let Ok(event) = rmp_serde::from_slice::<DataBaseEvent>(&value) {
+                            if event.event.time < time {
+                                removed.push((key.clone(), index::index_keys(&event.event)));
+                            }
+                        }
+                    }
+                    if scanned >= limit {
+                        next = Some(key.clone());
+                        break;
+                    }
+                }
+                iter.advance();
+            }
+        }
+        let mut batch = WriteBatch::new();
+        for (key, index_keys) in &removed {
+            batch.delete(key);
+            for index_key in index_keys {
+                batch.delete(index_key.as_bytes());
+            }
+        }
+        db.write(batch, false).map_err(resp_error::database_error)?;
+        Ok((removed.len(), next))
+    }
+    fn remove_image(&self, key: &[u8]) -> Result<(), RespError> {
+        let mut db = self.0.lock().unwrap();
+        db.delete(key).map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn remove_voice(&self, key: &[u8]) -> Result<(), RespError> {
+        let mut db = self.0.lock().unwrap();
+        db.delete(key).map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn remove_file(&self, key: &[u8]) -> Result<(), RespError> {
+        let mut db = self.0.lock().unwrap();
+        db.delete(&file_key(key))
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn remove_video(&self, key: &[u8]) -> Result<(), RespError> {
+        let mut db = self.0.lock().unwrap();
+        db.delete(&video_key(key))
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn flush(&self) -> Result<(), RespError> {
+        self.0
+            .lock()
+            .unwrap()
+            .flush()
+            .map_err(resp_error::database_error)
+    }
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(DumpRecord) -> Result<(), RespError>,
+    ) -> Result<(), RespError> {
+        let mut db = self.0.lock().unwrap();
+        let mut iter = db.new_iter().map_err(resp_error::database_error)?;
+        let (mut key, mut value) = (vec![], vec![]);
+        // messages, images and voices share one key space, tell them apart by content
+        while iter.advance() {
+            iter.current(&mut key, &mut value);
+            if index::is_index_key(&key) || key.starts_with(META_PREFIX) {
+                continue;
+            }
+            if key.starts_with(FILE_PREFIX) {
+                match rmp_serde::from_slice(&value) {
+                    Ok(file) => f(DumpRecord::File(file))?,
+                    Err(e) => tracing::warn!(
+                        target: crate::WALLE_Q,
+                        "skip corrupt file {}: {}",
+                        hex::encode(&key[FILE_PREFIX.len()..]),
+                        e
+                    ),
+                }
+            } else if key.starts_with(VIDEO_PREFIX) {
+                f(DumpRecord::video(&value))?;
+            } else if let Ok(event) = rmp_serde::from_slice::<DataBaseEvent>(&value) {
+                f(DumpRecord::message(event))?;
+            } else if rmp_serde::from_slice::<Images>(&value).is_ok() {
+                f(DumpRecord::image(&value))?;
+            } else if Voices::from_data(&value).is_some() {
+                f(DumpRecord::voice(&value))?;
+            } else {
+                tracing::warn!(
+                    target: crate::WALLE_Q,
+                    "skip unknown record {}",
+                    hex::encode(&key)
+                );
+            }
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+
+use walle_core::{
+    prelude::Event,
+    resp::{resp_error, RespError},
+};
+
+use super::{
+    index, DataBaseEvent, DataBaseEventRef, Database, DumpRecord, MessageId, SImage, SVoice,
+    StoredFile, Videos,
+};
+use crate::error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Message(String),
+    Image(Vec<u8>),
+    Voice(Vec<u8>),
+    File(Vec<u8>),
+    Video(Vec<u8>),
+}
+
+impl Key {
+    fn len(&self) -> usize {
+        match self {
+            Self::Message(key) => key.len(),
+            Self::Image(key) | Self::Voice(key) | Self::File(key) | Self::Video(key) => key.len(),
+        }
+    }
+}
+
+struct Record {
+    data: Vec<u8>,
+    index_keys: Vec<String>,
+    /// insert generation, an older entry in `order` means the record was rewritten
+    generation: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    records: HashMap<Key, Record>,
+    index: BTreeMap<String, String>,
+    order: VecDeque<(u64, Key)>,
+    generation: u64,
+    size: usize,
+}
+
+impl Inner {
+    fn insert(&mut self, key: Key, data: Vec<u8>, index_keys: Vec<String>) {
+        self.generation += 1;
+        self.size += key.len() + data.len();
+        if let Key::Message(message_id) = &key {
+            for index_key in &index_keys {
+                self.index.insert(index_key.clone(), message_id.clone());
+            }
+        }
+        let record = Record {
+            data,
+            index_keys,
+            generation: self.generation,
+        };
+        if let Some(old) = self.records.insert(key.clone(), record) {
+            self.size -= key.len() + old.data.len();
+        }
+        self.order.push_back((self.generation, key));
+    }
+
+    fn remove(&mut self, key: &Key) -> bool {
+        let Some(record) = self.records.remove(key) else {
+            return false;
+        };
+        self.size -= key.len() + record.data.len();
+        for index_key in &record.index_keys {
+            self.index.remove(index_key);
+        }
+        true
+    }
+
+    /// drop the oldest records until `size` fits in `max_size`
+    fn evict(&mut self, max_size: usize) {
+        while self.size > max_size {
+            let Some((generation, key)) = self.order.pop_front() else {
+                break;
+            };
+            if self.records.get(&key).map(|r| r.generation) == Some(generation) {
+                self.remove(&key);
+            }
+        }
+    }
+}
+
+/// in-memory backend, nothing survives a restart
+///
+/// with `max_size` the oldest records are evicted once the stored bytes exceed it
+pub(crate) struct MemoryDb {
+    inner: Mutex<Inner>,
+    max_size: Option<usize>,
+}
+
+impl MemoryDb {
+    pub fn new(max_size: Option<usize>) -> Self {
+        Self {
+            inner: Mutex::default(),
+            max_size,
+        }
+    }
+
+    fn insert(&self, key: Key, data: Vec<u8>, index_keys: Vec<String>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.insert(key, data, index_keys);
+        if let Some(max_size) = self.max_size {
+            inner.evict(max_size);
+        }
+    }
+
+    fn get(&self, key: &Key) -> Option<Vec<u8>> {
+        let inner = self.inner.lock().unwrap();
+        inner.records.get(key).map(|r| r.data.clone())
+    }
+}
+
+impl Database for MemoryDb {
+    fn get_message(&self, key: &str) -> Result<Option<DataBaseEvent>, RespError> {
+        self.get(&Key::Message(key.to_owned()))
+            .map(|v| rmp_serde::from_slice(&v).map_err(resp_error::database_error))
+            .transpose()
+    }
+    fn insert_message(
+        &self,
+        value: &Event,
+        seqs: Vec<i32>,
+        rands: Vec<i32>,
+    ) -> Result<(), RespError> {
+        let data = rmp_serde::to_vec(&DataBaseEventRef {
+            event: value,
+            seqs,
+            rands,
+        })
+        .map_err(resp_error::database_error)?;
+        self.insert(
+            Key::Message(value.message_id()),
+            data,
+            index::index_keys(value),
+        );
+        Ok(())
+    }
+    fn get_image<T>(&self, key: &[u8]) -> Result<Option<T>, RespError>
+    where
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        self.get(&Key::Image(key.to_vec()))
+            .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
+            .transpose()
+    }
+    fn insert_image<T>(&self, value: &T) -> Result<(), RespError>
+    where
+        T: serde::Serialize + SImage,
+    {
+        let data = rmp_serde::to_vec(value).map_err(resp_error::database_error)?;
+        self.insert(Key::Image(value.image_id()), data, vec![]);
+        Ok(())
+    }
+    fn get_voice<T: SVoice>(&self, key: &[u8]) -> Result<Option<T>, RespError> {
+        self.get(&Key::Voice(key.to_vec()))
+            .map(|v| SVoice::from_data(&v).ok_or_else(|| error::file_type_not_match("")))
+            .transpose()
+    }
+    fn insert_voice<T: SVoice>(&self, value: &T) -> Result<(), RespError> {
+        self.insert(Key::Voice(value.voice_id()), value.to_data(), vec![]);
+        Ok(())
+    }
+    fn get_file(&self, key: &[u8]) -> Result<Option<StoredFile>, RespError> {
+        self.get(&Key::File(key.to_vec()))
+            .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
+            .transpose()
+    }
+    fn insert_file(&self, value: &StoredFile) -> Result<(), RespError> {
+        let data = rmp_serde::to_vec(value).map_err(resp_error::database_error)?;
+        self.insert(Key::File(value.file_id()), data, vec![]);
+        Ok(())
+    }
+    fn get_video(&self, key: &[u8]) -> Result<Option<Videos>, RespError> {
+        self.get(&Key::Video(key.to_vec()))
+            .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
+            .transpose()
+    }
+    fn insert_video(&self, value: &Videos) -> Result<(), RespError> {
+        let data = rmp_serde::to_vec(value).map_err(resp_error::database_error)?;
+        self.insert(Key::Video(value.video_id()), data, vec![]);
+        Ok(())
+    }
+    fn scan_index(&self, start: &str, end: &str) -> Result<Vec<String>, RespError> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .index
+            .range(start.to_owned()..end.to_owned())
+            .map(|(_, id)| id.clone())
+            .collect())
+    }
+    fn put_index(&self, key: &str, value: &str) -> Result<(), RespError> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.index.insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+    fn remove_index(&self, key: &str) -> Result<(), RespError> {
+        self.inner.lock().unwrap().index.remove(key);
+        Ok(())
+    }
+    fn migrate_message_ids(&self) -> Result<usize, RespError> {
+        // starts empty every time, nothing to migrate
+        Ok(0)
+    }
+    fn remove_messages_before(
+        &self,
+        time: f64,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<(usize, Option<Vec<u8>>), RespError> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut keys: Vec<&String> = inner
+            .records
+            .keys()
+            .filter_map(|key| match key {
+                Key::Message(message_id)
+                    if after.map_or(true, |after| message_id.as_bytes() > after) =>
+                {
+                    Some(message_id)
+                }
+                _ => None,
+            })
+            .collect();
+        keys.sort_unstable();
+        keys.truncate(limit);
+        let next = keys
+            .last()
+            .filter(|_| keys.len() >= limit)
+            .map(|message_id| message_id.as_bytes().to_vec());
+        let removed: Vec<Key> = keys
+            .into_iter()
+            .map(|message_id| Key::Message(message_id.clone()))
+            .filter(|key| {
+                rmp_serde::from_slice::<DataBaseEvent>(&inner.records[key].data)
+                    .map_or(false, |m| m.event.time < time)
+            })
+            .collect();
+        for key in &removed {
+            inner.remove(key);
+        }
+        Ok((removed.len(), next))
+    }
+    fn remove_image(&self, key: &[u8]) -> Result<(), RespError> {
+        self.inner.lock().unwrap().remove(&Key::Image(key.to_vec()));
+        Ok(())
+    }
+    fn remove_voice(&self, key: &[u8]) -> Result<(), RespError> {
+        self.inner.lock().unwrap().remove(&Key::Voice(key.to_vec()));
+        Ok(())
+    }
+    fn remove_file(&self, key: &[u8]) -> Result<(), RespError> {
+        self.inner.lock().unwrap().remove(&Key::File(key.to_vec()));
+        Ok(())
+    }
+    fn remove_video(&self, key: &[u8]) -> Result<(), RespError> {
+        self.inner.lock().unwrap().remove(&Key::Video(key.to_vec()));
+        Ok(())
+    }
+    fn flush(&self) -> Result<(), RespError> {
+        Ok(())
+    }
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(DumpRecord) -> Result<(), RespError>,
+    ) -> Result<(), RespError> {
+        let inner = self.inner.lock().unwrap();
+        for (generation, key) in &inner.order {
+            let Some(record) = inner
+                .records
+                .get(key)
+                .filter(|r| r.generation == *generation)
+            else {
+                continue;
+            };
+            match key {
+                Key::Message(_) => match rmp_serde::from_slice(&record.data) {
+                    Ok(event) => f(DumpRecord::message(event))?,
+                    Err(e) => tracing::warn!(target: crate::WALLE_Q, "skip bad message: {}", e),
+                },
+                Key::Image(_) => f(DumpRecord::image(&record.data))?,
+                Key::Voice(_) => f(DumpRecord::voice(&record.data))?,
+                Key::Video(_) => f(DumpRecord::video(&record.data))?,
+                Key::File(_) => match rmp_serde::from_slice(&record.data) {
+                    Ok(file) => f(DumpRecord::File(file))?,
+                    Err(e) => tracing::warn!(target: crate::WALLE_Q, "skip bad file: {}", e),
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_memory_eviction() {
+    use super::LocalVoice;
+
+    let voice = |n: u8| LocalVoice {
+        md5: vec![n; 16],
+        size: n as u32,
+    };
+    let record_size = voice(0).voice_id().len() + voice(0).to_data().len();
+    let db = MemoryDb::new(Some(record_size * 2));
+    for n in 0..3 {
+        db.insert_voice(&voice(n)).unwrap();
+    }
+    assert!(db
+        .get_voice::<LocalVoice>(&voice(0).voice_id())
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        db.get_voice::<LocalVoice>(&voice(2).voice_id()).unwrap(),
+        Some(voice(2))
+    );
+
+    // rewriting a record makes it the newest one
+    db.insert_voice(&voice(1)).unwrap();
+    db.insert_voice(&voice(3)).unwrap();
+    assert!(db
+        .get_voice::<LocalVoice>(&voice(1).voice_id())
+        .unwrap()
+        .is_some());
+    assert!(db
+        .get_voice::<LocalVoice>(&voice(2).voice_id())
+        .unwrap()
+        .is_none());
+}
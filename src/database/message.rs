@@ -1,4 +1,6 @@
-use walle_core::{event::Event, util::ValueMapExt};
+use std::fmt::Display;
+
+use walle_core::{event::Event, structs::Selft, util::ValueMapExt, value};
 
 pub trait MessageId {
     fn message_id(&self) -> String;
@@ -9,3 +11,122 @@ impl MessageId for Event {
         self.extra.get_downcast("message_id").unwrap_or_default()
     }
 }
+
+/// recall state kept in the `recall` field of a stored message event
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecallState {
+    pub time: f64,
+    pub operator_id: String,
+}
+
+impl RecallState {
+    pub fn mark(&self, event: &mut Event) {
+        event.extra.insert(
+            "recall".to_owned(),
+            value!({
+                "time": self.time,
+                "operator_id": self.operator_id.clone(),
+            }),
+        );
+    }
+}
+
+/// message_id used when database is enabled
+///
+/// `{bot}:{detail_type}:{conversation}:{seq}`, conversation is group_code for group,
+/// friend uin for private and `{group_code}_{uin}` for group_temp
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredMessageId {
+    pub bot: String,
+    pub detail_type: String,
+    pub conversation: String,
+    pub seq: i32,
+}
+
+impl StoredMessageId {
+    pub fn group(bot: &str, group_code: i64, seq: i32) -> Self {
+        Self {
+            bot: bot.to_owned(),
+            detail_type: "group".to_owned(),
+            conversation: group_code.to_string(),
+            seq,
+        }
+    }
+
+    pub fn private(bot: &str, uin: i64, seq: i32) -> Self {
+        Self {
+            bot: bot.to_owned(),
+            detail_type: "private".to_owned(),
+            conversation: uin.to_string(),
+            seq,
+        }
+    }
+
+    pub fn group_temp(bot: &str, group_code: i64, uin: i64, seq: i32) -> Self {
+        Self {
+            bot: bot.to_owned(),
+            detail_type: "group_temp".to_owned(),
+            conversation: format!("{}_{}", group_code, uin),
+            seq,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut splits = s.split(':');
+        let id = Self {
+            bot: splits.next()?.to_owned(),
+            detail_type: splits.next()?.to_owned(),
+            conversation: splits.next()?.to_owned(),
+            seq: splits.next()?.parse().ok()?,
+        };
+        if splits.next().is_some() {
+            return None;
+        }
+        Some(id)
+    }
+
+    /// `{bot}:{detail_type}:{conversation}`, shared by all messages of a conversation
+    pub fn conversation_key(&self) -> String {
+        format!("{}:{}:{}", self.bot, self.detail_type, self.conversation)
+    }
+
+    /// rebuild the id from a stored message event, used to migrate legacy `seq` keys
+    pub fn from_event(event: &Event, seq: i32) -> Option<Self> {
+        let bot = event.extra.get_downcast::<Selft>("self").ok()?.user_id;
+        let get = |key: &str| -> Option<i64> {
+            event
+                .extra
+                .get_downcast::<String>(key)
+                .ok()
+                .and_then(|s| s.parse().ok())
+        };
+        let user_id = get("user_id")?;
+        match event.detail_type.as_str() {
+            "group" => Some(Self::group(&bot, get("group_id")?, seq)),
+            // user_id of a sent private message is the bot itself, the target is unknown
+            "private" if user_id.to_string() == bot => None,
+            "private" => Some(Self::private(&bot, user_id, seq)),
+            "group_temp" if user_id.to_string() == bot => None,
+            "group_temp" => Some(Self::group_temp(&bot, get("group_id")?, user_id, seq)),
+            _ => None,
+        }
+    }
+}
+
+impl Display for StoredMessageId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            self.bot, self.detail_type, self.conversation, self.seq
+        )
+    }
+}
+
+#[test]
+fn test_stored_message_id() {
+    let id = StoredMessageId::group_temp("10001", 20002, 30003, -4);
+    assert_eq!(id.to_string(), "10001:group_temp:20002_30003:-4");
+    assert_eq!(StoredMessageId::parse(&id.to_string()), Some(id));
+    assert_eq!(StoredMessageId::parse("1024"), None);
+}
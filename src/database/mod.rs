@@ -1,15 +1,31 @@
+pub(crate) mod dump;
+pub(crate) mod file;
+pub(crate) mod index;
 pub(crate) mod leveldb;
+pub(crate) mod memory;
 pub(crate) mod message;
+pub mod retention;
 pub(crate) mod simage;
 pub(crate) mod sleddb;
+pub(crate) mod sqlite;
+pub(crate) mod video;
 pub(crate) mod voice;
+pub(crate) mod worker;
 
+use std::cell::Cell;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+pub use dump::{check, export, import, migrate, BackendCheck, DumpRecord};
+pub use file::*;
+pub use index::{HistoryQuery, SearchQuery};
 pub use message::*;
 pub use simage::*;
+pub use video::*;
 pub use voice::*;
 use walle_core::{event::Event, resp::RespError};
+pub use worker::WQDatabase;
 
 pub(crate) trait DatabaseInit {
     fn init(base_path: &str) -> Self;
@@ -30,33 +46,140 @@ pub(crate) struct DataBaseEventRef<'a> {
 }
 
 pub(crate) trait Database {
-    fn get_message(&self, message_id: &str) -> Option<DataBaseEvent>;
-    fn insert_message(&self, value: &Event, seqs: Vec<i32>, rands: Vec<i32>);
+    fn get_message(&self, message_id: &str) -> Result<Option<DataBaseEvent>, RespError>;
+    fn insert_message(
+        &self,
+        value: &Event,
+        seqs: Vec<i32>,
+        rands: Vec<i32>,
+    ) -> Result<(), RespError>;
     fn get_image<T: for<'de> serde::Deserialize<'de>>(
         &self,
         key: &[u8],
     ) -> Result<Option<T>, RespError>;
-    fn insert_image<T: serde::Serialize + SImage>(&self, value: &T);
+    fn insert_image<T: serde::Serialize + SImage>(&self, value: &T) -> Result<(), RespError>;
     fn get_voice<T: SVoice>(&self, key: &[u8]) -> Result<Option<T>, RespError>;
-    fn insert_voice<T: SVoice>(&self, value: &T);
+    fn insert_voice<T: SVoice>(&self, value: &T) -> Result<(), RespError>;
+    fn get_file(&self, key: &[u8]) -> Result<Option<StoredFile>, RespError>;
+    fn insert_file(&self, value: &StoredFile) -> Result<(), RespError>;
+    fn get_video(&self, key: &[u8]) -> Result<Option<Videos>, RespError>;
+    fn insert_video(&self, value: &Videos) -> Result<(), RespError>;
+    /// message_ids of index keys in `[start, end)`, in key order
+    fn scan_index(&self, start: &str, end: &str) -> Result<Vec<String>, RespError>;
+    /// messages of a conversation in time order, latest `limit` within the range
+    fn get_message_history(&self, query: &HistoryQuery) -> Result<Vec<DataBaseEvent>, RespError> {
+        let (start, end) = query.index_range();
+        let mut messages = vec![];
+        for id in query.select(self.scan_index(&start, &end)?) {
+            if let Some(m) = self.get_message(&id)? {
+                messages.push(m);
+            }
+        }
+        Ok(messages)
+    }
+    /// messages containing the keyword, latest first
+    fn search_messages(&self, query: &SearchQuery) -> Result<Vec<DataBaseEvent>, RespError> {
+        let mut postings = vec![];
+        for term in index::query_terms(&query.keyword) {
+            let (start, end) = query.term_range(&term);
+            postings.push(self.scan_index(&start, &end)?);
+        }
+        let mut messages = vec![];
+        for id in query.intersect(postings).iter().rev() {
+            if messages.len() >= query.limit {
+                break;
+            }
+            match self.get_message(id)? {
+                Some(m) if query.matches(&m.event) => messages.push(m),
+                _ => {}
+            }
+        }
+        Ok(messages)
+    }
+    /// mark a stored message recalled, returns false if the message is not stored
+    fn mark_recalled(&self, message_id: &str, state: &RecallState) -> Result<bool, RespError> {
+        let Some(mut m) = self.get_message(message_id)? else {
+            return Ok(false);
+        };
+        state.mark(&mut m.event);
+        self.insert_message(&m.event, m.seqs, m.rands)?;
+        Ok(true)
+    }
+    /// put an entry into the index kept beside messages, e.g. a media age entry
+    fn put_index(&self, key: &str, value: &str) -> Result<(), RespError>;
+    fn remove_index(&self, key: &str) -> Result<(), RespError>;
+    /// rekey messages stored with legacy `seq` message_id to `StoredMessageId`
+    fn migrate_message_ids(&self) -> Result<usize, RespError>;
+    /// remove messages older than `time` with their index entries, scanning at most `limit`
+    /// records with keys after `after` in byte order. returns the removed count and the key
+    /// to continue after, `None` once the last record was scanned
+    fn remove_messages_before(
+        &self,
+        time: f64,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<(usize, Option<Vec<u8>>), RespError>;
+    fn remove_image(&self, key: &[u8]) -> Result<(), RespError>;
+    fn remove_voice(&self, key: &[u8]) -> Result<(), RespError>;
+    fn remove_file(&self, key: &[u8]) -> Result<(), RespError>;
+    fn remove_video(&self, key: &[u8]) -> Result<(), RespError>;
+    /// persist buffered writes
+    fn flush(&self) -> Result<(), RespError>;
+    /// visit every message, image, voice, file and video record, index entries are not visited
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(DumpRecord) -> Result<(), RespError>,
+    ) -> Result<(), RespError>;
+}
+
+/// returns the new message_id and the updated record if `key` is a legacy `seq` key
+pub(crate) fn migrate_legacy_message(
+    key: &str,
+    mut value: DataBaseEvent,
+) -> Option<(String, DataBaseEvent)> {
+    key.parse::<i32>().ok()?;
+    let message_id = StoredMessageId::from_event(&value.event, *value.seqs.first()?)?.to_string();
+    value
+        .event
+        .extra
+        .insert("message_id".to_owned(), message_id.clone().into());
+    Some((message_id, value))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    Sled,
+    Leveldb,
+    Sqlite,
 }
 
 pub(crate) enum WQDatabaseInner {
     SledDb(sleddb::SledDb),
     LevelDb(leveldb::LevelDb),
+    Sqlite(sqlite::SqliteDb),
+    Memory(memory::MemoryDb),
 }
 
 impl Database for WQDatabaseInner {
-    fn get_message(&self, key: &str) -> Option<DataBaseEvent> {
+    fn get_message(&self, key: &str) -> Result<Option<DataBaseEvent>, RespError> {
         match self {
             Self::SledDb(db) => db.get_message(key),
             Self::LevelDb(db) => db.get_message(key),
+            Self::Sqlite(db) => db.get_message(key),
+            Self::Memory(db) => db.get_message(key),
         }
     }
-    fn insert_message(&self, value: &Event, seqs: Vec<i32>, rands: Vec<i32>) {
+    fn insert_message(
+        &self,
+        value: &Event,
+        seqs: Vec<i32>,
+        rands: Vec<i32>,
+    ) -> Result<(), RespError> {
         match self {
             Self::SledDb(db) => db.insert_message(value, seqs, rands),
             Self::LevelDb(db) => db.insert_message(value, seqs, rands),
+            Self::Sqlite(db) => db.insert_message(value, seqs, rands),
+            Self::Memory(db) => db.insert_message(value, seqs, rands),
         }
     }
     fn get_image<T: for<'de> serde::Deserialize<'de>>(
@@ -66,95 +189,521 @@ impl Database for WQDatabaseInner {
         match self {
             Self::SledDb(db) => db.get_image(key),
             Self::LevelDb(db) => db.get_image(key),
+            Self::Sqlite(db) => db.get_image(key),
+            Self::Memory(db) => db.get_image(key),
         }
     }
-    fn insert_image<T: serde::Serialize + SImage>(&self, value: &T) {
+    fn insert_image<T: serde::Serialize + SImage>(&self, value: &T) -> Result<(), RespError> {
         match self {
             Self::SledDb(db) => db.insert_image(value),
             Self::LevelDb(db) => db.insert_image(value),
+            Self::Sqlite(db) => db.insert_image(value),
+            Self::Memory(db) => db.insert_image(value),
         }
     }
     fn get_voice<T: SVoice>(&self, key: &[u8]) -> Result<Option<T>, RespError> {
         match self {
             Self::SledDb(db) => db.get_voice(key),
             Self::LevelDb(db) => db.get_voice(key),
+            Self::Sqlite(db) => db.get_voice(key),
+            Self::Memory(db) => db.get_voice(key),
         }
     }
-    fn insert_voice<T: SVoice>(&self, value: &T) {
+    fn insert_voice<T: SVoice>(&self, value: &T) -> Result<(), RespError> {
         match self {
             Self::SledDb(db) => db.insert_voice(value),
             Self::LevelDb(db) => db.insert_voice(value),
+            Self::Sqlite(db) => db.insert_voice(value),
+            Self::Memory(db) => db.insert_voice(value),
+        }
+    }
+    fn get_file(&self, key: &[u8]) -> Result<Option<StoredFile>, RespError> {
+        match self {
+            Self::SledDb(db) => db.get_file(key),
+            Self::LevelDb(db) => db.get_file(key),
+            Self::Sqlite(db) => db.get_file(key),
+            Self::Memory(db) => db.get_file(key),
+        }
+    }
+    fn insert_file(&self, value: &StoredFile) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.insert_file(value),
+            Self::LevelDb(db) => db.insert_file(value),
+            Self::Sqlite(db) => db.insert_file(value),
+            Self::Memory(db) => db.insert_file(value),
+        }
+    }
+    fn get_video(&self, key: &[u8]) -> Result<Option<Videos>, RespError> {
+        match self {
+            Self::SledDb(db) => db.get_video(key),
+            Self::LevelDb(db) => db.get_video(key),
+            Self::Sqlite(db) => db.get_video(key),
+            Self::Memory(db) => db.get_video(key),
+        }
+    }
+    fn insert_video(&self, value: &Videos) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.insert_video(value),
+            Self::LevelDb(db) => db.insert_video(value),
+            Self::Sqlite(db) => db.insert_video(value),
+            Self::Memory(db) => db.insert_video(value),
+        }
+    }
+    fn scan_index(&self, start: &str, end: &str) -> Result<Vec<String>, RespError> {
+        match self {
+            Self::SledDb(db) => db.scan_index(start, end),
+            Self::LevelDb(db) => db.scan_index(start, end),
+            Self::Sqlite(db) => db.scan_index(start, end),
+            Self::Memory(db) => db.scan_index(start, end),
+        }
+    }
+    fn put_index(&self, key: &str, value: &str) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.put_index(key, value),
+            Self::LevelDb(db) => db.put_index(key, value),
+            Self::Sqlite(db) => db.put_index(key, value),
+            Self::Memory(db) => db.put_index(key, value),
+        }
+    }
+    fn remove_index(&self, key: &str) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.remove_index(key),
+            Self::LevelDb(db) => db.remove_index(key),
+            Self::Sqlite(db) => db.remove_index(key),
+            Self::Memory(db) => db.remove_index(key),
+        }
+    }
+    fn migrate_message_ids(&self) -> Result<usize, RespError> {
+        match self {
+            Self::SledDb(db) => db.migrate_message_ids(),
+            Self::LevelDb(db) => db.migrate_message_ids(),
+            Self::Sqlite(db) => db.migrate_message_ids(),
+            Self::Memory(db) => db.migrate_message_ids(),
+        }
+    }
+    fn remove_messages_before(
+        &self,
+        time: f64,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<(usize, Option<Vec<u8>>), RespError> {
+        match self {
+            Self::SledDb(db) => db.remove_messages_before(time, after, limit),
+            Self::LevelDb(db) => db.remove_messages_before(time, after, limit),
+            Self::Sqlite(db) => db.remove_messages_before(time, after, limit),
+            Self::Memory(db) => db.remove_messages_before(time, after, limit),
+        }
+    }
+    fn remove_image(&self, key: &[u8]) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.remove_image(key),
+            Self::LevelDb(db) => db.remove_image(key),
+            Self::Sqlite(db) => db.remove_image(key),
+            Self::Memory(db) => db.remove_image(key),
+        }
+    }
+    fn remove_voice(&self, key: &[u8]) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.remove_voice(key),
+            Self::LevelDb(db) => db.remove_voice(key),
+            Self::Sqlite(db) => db.remove_voice(key),
+            Self::Memory(db) => db.remove_voice(key),
+        }
+    }
+    fn remove_file(&self, key: &[u8]) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.remove_file(key),
+            Self::LevelDb(db) => db.remove_file(key),
+            Self::Sqlite(db) => db.remove_file(key),
+            Self::Memory(db) => db.remove_file(key),
+        }
+    }
+    fn remove_video(&self, key: &[u8]) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.remove_video(key),
+            Self::LevelDb(db) => db.remove_video(key),
+            Self::Sqlite(db) => db.remove_video(key),
+            Self::Memory(db) => db.remove_video(key),
+        }
+    }
+    fn flush(&self) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.flush(),
+            Self::LevelDb(db) => db.flush(),
+            Self::Sqlite(db) => db.flush(),
+            Self::Memory(db) => db.flush(),
+        }
+    }
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(DumpRecord) -> Result<(), RespError>,
+    ) -> Result<(), RespError> {
+        match self {
+            Self::SledDb(db) => db.for_each_record(f),
+            Self::LevelDb(db) => db.for_each_record(f),
+            Self::Sqlite(db) => db.for_each_record(f),
+            Self::Memory(db) => db.for_each_record(f),
         }
     }
 }
 
-// insert all but read the first
-// #[derive(Default)]
-pub struct WQDatabase(Vec<WQDatabaseInner>, Arc<String>);
+// insert all but read the first, owned by the worker behind `WQDatabase`
+pub(crate) struct Storage {
+    backends: Vec<WQDatabaseInner>,
+    base_path: Arc<String>,
+    read_repair: bool,
+    repaired: Cell<bool>,
+}
 
-impl WQDatabase {
+impl Storage {
     pub fn new(base_path: &Arc<String>) -> Self {
-        Self(vec![], base_path.clone())
+        Self {
+            backends: vec![],
+            base_path: base_path.clone(),
+            read_repair: false,
+            repaired: Cell::new(false),
+        }
     }
     pub fn add_sled(mut self) -> Self {
-        self.0
-            .push(WQDatabaseInner::SledDb(sleddb::SledDb::init(&self.1)));
+        let db = sleddb::SledDb::init(&self.base_path);
+        self.backends.push(WQDatabaseInner::SledDb(db));
         self
     }
     pub fn add_level(mut self) -> Self {
-        self.0
-            .push(WQDatabaseInner::LevelDb(leveldb::LevelDb::init(&self.1)));
+        let db = leveldb::LevelDb::init(&self.base_path);
+        self.backends.push(WQDatabaseInner::LevelDb(db));
+        self
+    }
+    pub fn add_sqlite(mut self) -> Self {
+        let db = sqlite::SqliteDb::init(&self.base_path);
+        self.backends.push(WQDatabaseInner::Sqlite(db));
+        self
+    }
+    /// `max_size` in bytes, the oldest records are evicted beyond it
+    pub fn add_memory(mut self, max_size: Option<usize>) -> Self {
+        let db = memory::MemoryDb::new(max_size);
+        self.backends.push(WQDatabaseInner::Memory(db));
+        self
+    }
+    pub fn add(self, backend: Backend) -> Self {
+        match backend {
+            Backend::Sled => self.add_sled(),
+            Backend::Leveldb => self.add_level(),
+            Backend::Sqlite => self.add_sqlite(),
+        }
+    }
+    /// back-fill records found in a later backend into the backends read before it
+    pub fn read_repair(mut self, enable: bool) -> Self {
+        self.read_repair = enable;
         self
     }
     pub fn not_empty(&self) -> bool {
-        !self.0.is_empty()
+        !self.backends.is_empty()
     }
-}
-
-impl Database for WQDatabase {
-    fn insert_message(&self, value: &Event, seqs: Vec<i32>, rands: Vec<i32>) {
-        for db in &self.0 {
-            db.insert_message(value, seqs.clone(), rands.clone())
+    /// whether read repair wrote anything since the last call
+    pub fn take_repaired(&self) -> bool {
+        self.repaired.replace(false)
+    }
+    /// write to every backend, a failed backend does not stop the others
+    fn write_all(
+        &self,
+        f: impl Fn(&WQDatabaseInner) -> Result<(), RespError>,
+    ) -> Result<(), RespError> {
+        let mut result = Ok(());
+        for db in &self.backends {
+            if let Err(e) = f(db) {
+                result = Err(e);
+            }
         }
+        result
     }
-    fn get_message(&self, key: &str) -> Option<DataBaseEvent> {
-        for db in &self.0 {
-            match db.get_message(key) {
-                Some(v) => return Some(v),
-                None => continue,
+    /// write a record missed by the first `lagging` backends, failures only logged
+    fn repair(
+        &self,
+        lagging: usize,
+        key: &dyn std::fmt::Display,
+        f: impl Fn(&WQDatabaseInner) -> Result<(), RespError>,
+    ) {
+        if !self.read_repair || lagging == 0 {
+            return;
+        }
+        let mut repaired = 0;
+        for db in &self.backends[..lagging] {
+            match f(db) {
+                Ok(()) => repaired += 1,
+                Err(e) => tracing::warn!(
+                    target: crate::WALLE_Q,
+                    "read repair of {} failed: {}",
+                    key,
+                    e.message
+                ),
             }
         }
-        None
+        if repaired > 0 {
+            self.repaired.set(true);
+            tracing::debug!(target: crate::WALLE_Q, "read repaired {} into {} backends", key, repaired);
+        }
     }
-    fn insert_image<T: serde::Serialize + SImage>(&self, value: &T) {
-        for db in &self.0 {
-            db.insert_image(value)
+    /// index the time a media record is stored, retention prunes records by it
+    fn index_media(&self, kind: &str, id: &str) -> Result<(), RespError> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        let key = index::media_index_key(time, kind, id);
+        let time_key = index::media_time_key(kind, id);
+        self.write_all(|db| {
+            db.put_index(&key, &key)?;
+            db.put_index(&time_key, &format!("{:012}", time))
+        })
+    }
+    /// results of every backend merged by message_id, the copy of the earlier backend is kept
+    ///
+    /// the memory backend or a lagging backend only holds part of the messages, so no
+    /// backend alone is trusted to be complete. a failing backend is skipped
+    fn query_all(
+        &self,
+        f: impl Fn(&WQDatabaseInner) -> Result<Vec<DataBaseEvent>, RespError>,
+    ) -> Result<Vec<DataBaseEvent>, RespError> {
+        let mut seen = HashSet::new();
+        let mut messages = vec![];
+        let mut error = None;
+        let mut succeeded = false;
+        for (i, db) in self.backends.iter().enumerate() {
+            match f(db) {
+                Ok(v) => {
+                    succeeded = true;
+                    messages.extend(v.into_iter().filter(|m| seen.insert(m.event.message_id())));
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        target: crate::WALLE_Q,
+                        "query backend {} failed: {}",
+                        i,
+                        e.message
+                    );
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match error {
+            Some(e) if !succeeded => Err(e),
+            _ => Ok(messages),
         }
     }
+    /// index and value of the first backend having `key`
+    ///
+    /// a failing backend is skipped so a corrupt record is read, and repaired, from the
+    /// next one, the first error is returned only if no backend has the record
+    fn read_first<T>(
+        &self,
+        key: &dyn std::fmt::Display,
+        f: impl Fn(&WQDatabaseInner) -> Result<Option<T>, RespError>,
+    ) -> Result<Option<(usize, T)>, RespError> {
+        let mut error = None;
+        for (i, db) in self.backends.iter().enumerate() {
+            match f(db) {
+                Ok(Some(v)) => return Ok(Some((i, v))),
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!(
+                        target: crate::WALLE_Q,
+                        "read {} from backend {} failed: {}",
+                        key,
+                        i,
+                        e.message
+                    );
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        error.map_or(Ok(None), Err)
+    }
+}
+
+impl Database for Storage {
+    fn insert_message(
+        &self,
+        value: &Event,
+        seqs: Vec<i32>,
+        rands: Vec<i32>,
+    ) -> Result<(), RespError> {
+        self.write_all(|db| db.insert_message(value, seqs.clone(), rands.clone()))
+    }
+    fn get_message(&self, key: &str) -> Result<Option<DataBaseEvent>, RespError> {
+        let Some((i, v)) = self.read_first(&key, |db| db.get_message(key))? else {
+            return Ok(None);
+        };
+        self.repair(i, &key, |db| {
+            db.insert_message(&v.event, v.seqs.clone(), v.rands.clone())
+        });
+        Ok(Some(v))
+    }
+    fn insert_image<T: serde::Serialize + SImage>(&self, value: &T) -> Result<(), RespError> {
+        self.write_all(|db| db.insert_image(value))?;
+        self.index_media(index::MEDIA_IMAGE, &value.hex_image_id())
+    }
     fn get_image<T: for<'de> serde::Deserialize<'de>>(
         &self,
         key: &[u8],
     ) -> Result<Option<T>, RespError> {
-        for db in &self.0 {
-            match db.get_image(key)? {
-                Some(v) => return Ok(Some(v)),
-                None => continue,
+        let hex_key = hex::encode(key);
+        let Some((i, v)) = self.read_first(&hex_key, |db| db.get_image::<T>(key))? else {
+            return Ok(None);
+        };
+        // `T` may be a partial view, repair with the full record
+        if self.read_repair && i > 0 {
+            if let Ok(Some(image)) = self.backends[i].get_image::<Images>(key) {
+                self.repair(i, &hex_key, |db| db.insert_image(&image));
             }
         }
-        Ok(None)
+        Ok(Some(v))
     }
-    fn insert_voice<T: SVoice>(&self, value: &T) {
-        for db in &self.0 {
-            db.insert_voice(value)
-        }
+    fn insert_voice<T: SVoice>(&self, value: &T) -> Result<(), RespError> {
+        self.write_all(|db| db.insert_voice(value))?;
+        self.index_media(index::MEDIA_VOICE, &value.hex_voice_id())
     }
     fn get_voice<T: SVoice>(&self, key: &[u8]) -> Result<Option<T>, RespError> {
-        for db in &self.0 {
-            match db.get_voice(key)? {
-                Some(v) => return Ok(Some(v)),
-                None => continue,
+        let hex_key = hex::encode(key);
+        let Some((i, v)) = self.read_first(&hex_key, |db| db.get_voice::<T>(key))? else {
+            return Ok(None);
+        };
+        self.repair(i, &hex_key, |db| db.insert_voice(&v));
+        Ok(Some(v))
+    }
+    fn get_file(&self, key: &[u8]) -> Result<Option<StoredFile>, RespError> {
+        let hex_key = hex::encode(key);
+        let Some((i, v)) = self.read_first(&hex_key, |db| db.get_file(key))? else {
+            return Ok(None);
+        };
+        self.repair(i, &hex_key, |db| db.insert_file(&v));
+        Ok(Some(v))
+    }
+    fn insert_file(&self, value: &StoredFile) -> Result<(), RespError> {
+        self.write_all(|db| db.insert_file(value))
+    }
+    fn get_video(&self, key: &[u8]) -> Result<Option<Videos>, RespError> {
+        let hex_key = hex::encode(key);
+        let Some((i, v)) = self.read_first(&hex_key, |db| db.get_video(key))? else {
+            return Ok(None);
+        };
+        self.repair(i, &hex_key, |db| db.insert_video(&v));
+        Ok(Some(v))
+    }
+    fn insert_video(&self, value: &Videos) -> Result<(), RespError> {
+        self.write_all(|db| db.insert_video(value))?;
+        self.index_media(index::MEDIA_VIDEO, &value.hex_video_id())
+    }
+    fn scan_index(&self, start: &str, end: &str) -> Result<Vec<String>, RespError> {
+        // ids of different backends can not be merged in key order, take the most complete
+        let mut longest = vec![];
+        let mut error = None;
+        let mut succeeded = false;
+        for (i, db) in self.backends.iter().enumerate() {
+            match db.scan_index(start, end) {
+                Ok(ids) => {
+                    succeeded = true;
+                    if ids.len() > longest.len() {
+                        longest = ids;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        target: crate::WALLE_Q,
+                        "scan index of backend {} failed: {}",
+                        i,
+                        e.message
+                    );
+                    error.get_or_insert(e);
+                }
             }
         }
-        Ok(None)
+        match error {
+            Some(e) if !succeeded => Err(e),
+            _ => Ok(longest),
+        }
+    }
+    fn get_message_history(&self, query: &HistoryQuery) -> Result<Vec<DataBaseEvent>, RespError> {
+        let mut messages = self.query_all(|db| db.get_message_history(query))?;
+        messages.sort_by(|a, b| {
+            a.event
+                .time
+                .total_cmp(&b.event.time)
+                .then_with(|| a.seqs.first().cmp(&b.seqs.first()))
+        });
+        messages.drain(..messages.len().saturating_sub(query.limit));
+        Ok(messages)
+    }
+    fn search_messages(&self, query: &SearchQuery) -> Result<Vec<DataBaseEvent>, RespError> {
+        let mut messages = self.query_all(|db| db.search_messages(query))?;
+        messages.sort_by(|a, b| b.event.time.total_cmp(&a.event.time));
+        messages.truncate(query.limit);
+        Ok(messages)
+    }
+    fn put_index(&self, key: &str, value: &str) -> Result<(), RespError> {
+        self.write_all(|db| db.put_index(key, value))
+    }
+    fn remove_index(&self, key: &str) -> Result<(), RespError> {
+        self.write_all(|db| db.remove_index(key))
+    }
+    fn migrate_message_ids(&self) -> Result<usize, RespError> {
+        let mut count = 0;
+        for db in &self.backends {
+            count += db.migrate_message_ids()?;
+        }
+        // runs before the worker starts, persist it here
+        self.flush()?;
+        if count > 0 {
+            tracing::info!(
+                target: crate::WALLE_Q,
+                "migrated {} stored messages to new message_id",
+                count
+            );
+        }
+        Ok(count)
+    }
+    fn remove_messages_before(
+        &self,
+        time: f64,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<(usize, Option<Vec<u8>>), RespError> {
+        // every backend stores the same messages, count those of the first one. the scan
+        // continues after the least advanced backend so none of them skips a record
+        let mut count = None;
+        let mut next: Option<Vec<u8>> = None;
+        for db in &self.backends {
+            let (removed, db_next) = db.remove_messages_before(time, after, limit)?;
+            count.get_or_insert(removed);
+            next = match (next, db_next) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        Ok((count.unwrap_or_default(), next))
+    }
+    fn remove_image(&self, key: &[u8]) -> Result<(), RespError> {
+        self.write_all(|db| db.remove_image(key))
+    }
+    fn remove_voice(&self, key: &[u8]) -> Result<(), RespError> {
+        self.write_all(|db| db.remove_voice(key))
+    }
+    fn remove_file(&self, key: &[u8]) -> Result<(), RespError> {
+        self.write_all(|db| db.remove_file(key))
+    }
+    fn remove_video(&self, key: &[u8]) -> Result<(), RespError> {
+        self.write_all(|db| db.remove_video(key))
+    }
+    fn flush(&self) -> Result<(), RespError> {
+        self.write_all(|db| db.flush())
+    }
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(DumpRecord) -> Result<(), RespError>,
+    ) -> Result<(), RespError> {
+        match self.backends.first() {
+            Some(db) => db.for_each_record(f),
+            None => Ok(()),
+        }
     }
 }
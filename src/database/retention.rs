@@ -0,0 +1,333 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::de::IgnoredAny;
+use tracing::{info, warn};
+use walle_core::resp::RespError;
+
+use super::index::{self, media_index_key, media_time_key, parse_media_index_key};
+use super::{Database, Storage, Voices, WQDatabase};
+use crate::config::RetentionConfig;
+use crate::WALLE_Q;
+
+/// record lookups per worker job
+const LOOKUP_CHUNK: usize = 256;
+/// records scanned for expired messages per worker job
+const REMOVE_CHUNK: usize = 1024;
+
+/// what one sweep removed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SweepReport {
+    pub messages: usize,
+    pub images: usize,
+    pub voices: usize,
+    pub videos: usize,
+    pub files: usize,
+    /// image, voice and video records stored before the deadline, e.g. of received media
+    pub records: usize,
+    /// media files without database record, included in the counts above
+    pub orphans: usize,
+    pub bytes: u64,
+}
+
+impl SweepReport {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl Display for SweepReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "removed {} messages, {} media records, {} images, {} voices, {} videos, {} files ({} orphaned), freed {} bytes",
+            self.messages,
+            self.records,
+            self.images,
+            self.voices,
+            self.videos,
+            self.files,
+            self.orphans,
+            self.bytes
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MediaKind {
+    Image,
+    Voice,
+    Video,
+    File,
+}
+
+impl MediaKind {
+    fn from_index(kind: &str) -> Option<Self> {
+        match kind {
+            index::MEDIA_IMAGE => Some(Self::Image),
+            index::MEDIA_VOICE => Some(Self::Voice),
+            index::MEDIA_VIDEO => Some(Self::Video),
+            _ => None,
+        }
+    }
+
+    fn referenced(self, db: &Storage, id: &[u8]) -> bool {
+        // a record failed to decode still counts as referenced
+        match self {
+            Self::Image => !matches!(db.get_image::<IgnoredAny>(id), Ok(None)),
+            Self::Voice => !matches!(db.get_voice::<Voices>(id), Ok(None)),
+            Self::Video => !matches!(db.get_video(id), Ok(None)),
+            Self::File => !matches!(db.get_file(id), Ok(None)),
+        }
+    }
+
+    fn remove_record(self, db: &Storage, id: &[u8]) -> Result<(), RespError> {
+        match self {
+            Self::Image => db.remove_image(id),
+            Self::Voice => db.remove_voice(id),
+            Self::Video => db.remove_video(id),
+            Self::File => db.remove_file(id),
+        }
+    }
+}
+
+struct MediaFile {
+    kind: MediaKind,
+    id: Vec<u8>,
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+fn media_files(base_path: &str, dir: &str, kind: MediaKind) -> Vec<MediaFile> {
+    let Ok(entries) = std::fs::read_dir(format!("{}/{}", base_path, dir)) else {
+        return vec![];
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some(MediaFile {
+                kind,
+                id: hex::decode(entry.file_name().to_str()?).ok()?,
+                path: entry.path(),
+                size: meta.len(),
+                modified: meta.modified().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// every media file, oldest first
+fn scan_media(base_path: &str) -> Vec<MediaFile> {
+    let mut files = media_files(base_path, crate::IMAGE_DIR, MediaKind::Image);
+    files.extend(media_files(base_path, crate::VOICE_DIR, MediaKind::Voice));
+    files.extend(media_files(base_path, crate::VIDEO_DIR, MediaKind::Video));
+    files.extend(media_files(base_path, crate::FILE_DIR, MediaKind::File));
+    files.sort_by_key(|file| file.modified);
+    files
+}
+
+/// whether each file modified before `grace` has a database record, newer files count as
+/// referenced. looked up in chunks so other jobs run in between
+async fn referenced(db: &WQDatabase, files: &[MediaFile], grace: SystemTime) -> Vec<bool> {
+    let mut referenced = Vec::with_capacity(files.len());
+    for chunk in files.chunks(LOOKUP_CHUNK) {
+        let keys: Vec<_> = chunk
+            .iter()
+            .map(|file| (file.modified < grace).then(|| (file.kind, file.id.clone())))
+            .collect();
+        let result = db
+            .read(move |s| {
+                Ok(keys
+                    .iter()
+                    .map(|key| {
+                        key.as_ref()
+                            .map_or(true, |(kind, id)| kind.referenced(s, id))
+                    })
+                    .collect::<Vec<_>>())
+            })
+            .await;
+        match result {
+            Ok(chunk) => referenced.extend(chunk),
+            Err(e) => {
+                // keep the files if the lookup failed
+                warn!(target: WALLE_Q, "lookup media records failed: {}", e.message);
+                referenced.extend(std::iter::repeat(true).take(chunk.len()));
+            }
+        }
+    }
+    referenced
+}
+
+/// remove image, voice and video records stored before `deadline` by the media age index.
+/// received media has no local file, so its records are only pruned here. a record stored
+/// again later is kept until its latest entry expires
+async fn prune_records(db: &WQDatabase, deadline: i64) -> usize {
+    let keys = db
+        .read(move |s| {
+            s.scan_index(
+                &media_index_key(0, "", ""),
+                &media_index_key(deadline, "", ""),
+            )
+        })
+        .await;
+    let keys = match keys {
+        Ok(keys) => keys,
+        Err(e) => {
+            warn!(target: WALLE_Q, "scan media records failed: {}", e.message);
+            return 0;
+        }
+    };
+    let mut removed = 0;
+    for chunk in keys.chunks(LOOKUP_CHUNK) {
+        let chunk = chunk.to_vec();
+        let result = db
+            .modify(move |s| {
+                let mut removed = 0;
+                for key in &chunk {
+                    if let Some((time, kind, id)) = parse_media_index_key(key) {
+                        let time_key = media_time_key(kind, id);
+                        let latest = s.scan_index(&time_key, &format!("{}\0", time_key))?;
+                        if latest.first() == Some(&format!("{:012}", time)) {
+                            if let (Some(kind), Ok(id)) =
+                                (MediaKind::from_index(kind), hex::decode(id))
+                            {
+                                kind.remove_record(s, &id)?;
+                                removed += 1;
+                            }
+                            s.remove_index(&time_key)?;
+                        }
+                    }
+                    s.remove_index(key)?;
+                }
+                Ok(removed)
+            })
+            .await;
+        match result {
+            Ok(count) => removed += count,
+            Err(e) => {
+                warn!(target: WALLE_Q, "remove media records failed: {}", e.message);
+                break;
+            }
+        }
+    }
+    removed
+}
+
+/// prune expired messages, expired media records, expired / oversized media files and orphaned media files
+///
+/// media records are removed by the time they were stored, media files are removed oldest first together with their database records, orphaned
+/// files are only removed after one `interval` so files being uploaded are kept.
+/// the data directory is scanned and files are removed off the database worker, only
+/// record lookups and removals are queued on it. messages are removed in chunks so other
+/// jobs run in between
+pub(crate) async fn sweep(
+    db: &WQDatabase,
+    base_path: &str,
+    config: &RetentionConfig,
+) -> SweepReport {
+    let now = SystemTime::now();
+    let mut report = SweepReport::default();
+    let deadline = config
+        .max_age_days
+        .map(|days| now - Duration::from_secs(days * 24 * 60 * 60));
+    if let Some(deadline) = deadline {
+        let time = deadline
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let mut after = None;
+        loop {
+            let cursor = after.take();
+            let result = db
+                .modify(move |s| s.remove_messages_before(time, cursor.as_deref(), REMOVE_CHUNK))
+                .await;
+            match result {
+                Ok((count, next)) => {
+                    report.messages += count;
+                    match next {
+                        Some(next) => after = Some(next),
+                        None => break,
+                    }
+                }
+                Err(e) => {
+                    warn!(target: WALLE_Q, "remove messages failed: {}", e.message);
+                    break;
+                }
+            }
+        }
+        if db.not_empty() {
+            let secs = deadline
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            report.records = prune_records(db, secs as i64).await;
+        }
+    }
+
+    let scan_path = base_path.to_owned();
+    let files = match tokio::task::spawn_blocking(move || scan_media(&scan_path)).await {
+        Ok(files) => files,
+        Err(e) => {
+            warn!(target: WALLE_Q, "scan media files failed: {}", e);
+            return report;
+        }
+    };
+    let max_size = config.max_media_size_mb.map(|mb| mb * 1024 * 1024);
+    let grace = now - Duration::from_secs(config.interval);
+    let referenced = if db.not_empty() {
+        referenced(db, &files, grace).await
+    } else {
+        vec![true; files.len()]
+    };
+    let mut total: u64 = files.iter().map(|file| file.size).sum();
+    for (file, referenced) in files.into_iter().zip(referenced) {
+        let expired = deadline.map_or(false, |deadline| file.modified < deadline)
+            || max_size.map_or(false, |max_size| total > max_size);
+        let orphan = !expired && !referenced;
+        if !expired && !orphan {
+            continue;
+        }
+        if let Err(e) = tokio::fs::remove_file(&file.path).await {
+            warn!(target: WALLE_Q, "remove {} failed: {}", file.path.display(), e);
+            continue;
+        }
+        total -= file.size;
+        report.bytes += file.size;
+        if orphan {
+            report.orphans += 1;
+        }
+        match file.kind {
+            MediaKind::Image => report.images += 1,
+            MediaKind::Voice => report.voices += 1,
+            MediaKind::Video => report.videos += 1,
+            MediaKind::File => report.files += 1,
+        }
+        if db.not_empty() {
+            // failures are logged by the worker
+            let (kind, id) = (file.kind, file.id);
+            db.write(move |s| kind.remove_record(s, &id));
+        }
+    }
+    report
+}
+
+/// run `sweep` every `config.interval` seconds if retention is enabled
+pub fn spawn_sweeper(db: Arc<WQDatabase>, base_path: Arc<String>, config: RetentionConfig) {
+    if !config.enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval.max(60)));
+        loop {
+            interval.tick().await;
+            let report = sweep(&db, &base_path, &config).await;
+            if !report.is_empty() {
+                info!(target: WALLE_Q, "retention sweep {}", report);
+            }
+        }
+    });
+}
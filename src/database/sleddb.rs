@@ -2,13 +2,22 @@ use crate::error;
 
 use super::*;
 use sled::Tree;
+use std::ops::Bound;
+use walle_core::resp::resp_error;
 
 pub(crate) struct SledDb {
     pub message_tree: Tree,
     pub image_tree: Tree,
     pub audio_tree: Tree,
+    pub file_tree: Tree,
+    pub video_tree: Tree,
+    pub index_tree: Tree,
+    pub meta_tree: Tree,
 }
 
+/// written to `meta_tree` once legacy message ids are migrated
+const MIGRATED_KEY: &[u8] = b"migrated_message_ids";
+
 impl DatabaseInit for SledDb {
     fn init(base_path: &str) -> Self {
         let s = sled::open(format!("{}/{}", base_path, "sled")).unwrap();
@@ -16,30 +25,47 @@ impl DatabaseInit for SledDb {
             message_tree: s.open_tree("message").unwrap(),
             image_tree: s.open_tree("image").unwrap(),
             audio_tree: s.open_tree("audio").unwrap(),
+            file_tree: s.open_tree("file").unwrap(),
+            video_tree: s.open_tree("video").unwrap(),
+            index_tree: s.open_tree("index").unwrap(),
+            meta_tree: s.open_tree("meta").unwrap(),
         }
     }
 }
 
 impl Database for SledDb {
-    fn get_message(&self, key: &str) -> Option<DataBaseEvent> {
+    fn get_message(&self, key: &str) -> Result<Option<DataBaseEvent>, RespError> {
         self.message_tree
             .get(key.as_bytes())
-            .unwrap()
-            .map(|v| rmp_serde::from_slice(&v).unwrap())
+            .map_err(resp_error::database_error)?
+            .map(|v| rmp_serde::from_slice(&v).map_err(resp_error::database_error))
+            .transpose()
     }
 
-    fn insert_message(&self, value: &Event, seqs: Vec<i32>, rands: Vec<i32>) {
+    fn insert_message(
+        &self,
+        value: &Event,
+        seqs: Vec<i32>,
+        rands: Vec<i32>,
+    ) -> Result<(), RespError> {
+        let message_id = value.message_id();
         self.message_tree
             .insert(
-                value.message_id().as_bytes(),
+                message_id.as_bytes(),
                 rmp_serde::to_vec(&DataBaseEventRef {
                     event: value,
                     seqs,
                     rands,
                 })
-                .unwrap(),
+                .map_err(resp_error::database_error)?,
             )
-            .unwrap();
+            .map_err(resp_error::database_error)?;
+        for key in index::index_keys(value) {
+            self.index_tree
+                .insert(key.as_bytes(), message_id.as_bytes())
+                .map_err(resp_error::database_error)?;
+        }
+        Ok(())
     }
 
     fn get_image<T>(&self, key: &[u8]) -> Result<Option<T>, RespError>
@@ -48,29 +74,222 @@ impl Database for SledDb {
     {
         self.image_tree
             .get(key)
-            .unwrap()
+            .map_err(resp_error::database_error)?
             .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
             .transpose()
     }
 
-    fn insert_image<T>(&self, value: &T)
+    fn insert_image<T>(&self, value: &T) -> Result<(), RespError>
     where
         T: serde::Serialize + SImage,
     {
         self.image_tree
-            .insert(value.image_id(), rmp_serde::to_vec(value).unwrap())
-            .unwrap();
+            .insert(
+                value.image_id(),
+                rmp_serde::to_vec(value).map_err(resp_error::database_error)?,
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
     }
     fn get_voice<T: SVoice>(&self, key: &[u8]) -> Result<Option<T>, RespError> {
         self.audio_tree
             .get(key)
-            .unwrap()
+            .map_err(resp_error::database_error)?
             .map(|v| SVoice::from_data(&v.to_vec()).ok_or_else(|| error::file_type_not_match("")))
             .transpose()
     }
-    fn insert_voice<T: SVoice>(&self, value: &T) {
+    fn insert_voice<T: SVoice>(&self, value: &T) -> Result<(), RespError> {
         self.audio_tree
             .insert(value.voice_id(), value.to_data())
-            .unwrap();
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn get_file(&self, key: &[u8]) -> Result<Option<StoredFile>, RespError> {
+        self.file_tree
+            .get(key)
+            .map_err(resp_error::database_error)?
+            .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
+            .transpose()
+    }
+    fn insert_file(&self, value: &StoredFile) -> Result<(), RespError> {
+        self.file_tree
+            .insert(
+                value.file_id(),
+                rmp_serde::to_vec(value).map_err(resp_error::database_error)?,
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn get_video(&self, key: &[u8]) -> Result<Option<Videos>, RespError> {
+        self.video_tree
+            .get(key)
+            .map_err(resp_error::database_error)?
+            .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
+            .transpose()
+    }
+    fn insert_video(&self, value: &Videos) -> Result<(), RespError> {
+        self.video_tree
+            .insert(
+                value.video_id(),
+                rmp_serde::to_vec(value).map_err(resp_error::database_error)?,
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn scan_index(&self, start: &str, end: &str) -> Result<Vec<String>, RespError> {
+        self.index_tree
+            .range(start.as_bytes()..end.as_bytes())
+            .values()
+            .map(|v| v.map(|v| String::from_utf8_lossy(&v).into_owned()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(resp_error::database_error)
+    }
+    fn put_index(&self, key: &str, value: &str) -> Result<(), RespError> {
+        self.index_tree
+            .insert(key.as_bytes(), value.as_bytes())
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn remove_index(&self, key: &str) -> Result<(), RespError> {
+        self.index_tree
+            .remove(key.as_bytes())
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn migrate_message_ids(&self) -> Result<usize, RespError> {
+        if self
+            .meta_tree
+            .contains_key(MIGRATED_KEY)
+            .map_err(resp_error::database_error)?
+        {
+            return Ok(0);
+        }
+        let mut count = 0;
+        for entry in self.message_tree.iter() {
+            let (key, value) = entry.map_err(resp_error::database_error)?;
+            let Ok(old) = std::str::from_utf8(&key) else {
+                continue;
+            };
+            let Ok(event) = rmp_serde::from_slice(&value) else {
+                continue;
+            };
+            if let Some((_, event)) = migrate_legacy_message(old, event) {
+                self.insert_message(&event.event, event.seqs, event.rands)?;
+                self.message_tree
+                    .remove(&key)
+                    .map_err(resp_error::database_error)?;
+                count += 1;
+            }
+        }
+        self.meta_tree
+            .insert(MIGRATED_KEY, b"1")
+            .map_err(resp_error::database_error)?;
+        Ok(count)
+    }
+    fn remove_messages_before(
+        &self,
+        time: f64,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<(usize, Option<Vec<u8>>), RespError> {
+        let iter = match after {
+            Some(after) => self
+                .message_tree
+                .range::<&[u8], _>((Bound::Excluded(after), Bound::Unbounded)),
+            None => self.message_tree.iter(),
+        };
+        let (mut count, mut scanned, mut last) = (0, 0, None);
+        for entry in iter.take(limit) {
+            let (key, value) = entry.map_err(resp_error::database_error)?;
+            scanned += 1;
+            if let Ok(event) = rmp_serde::from_slice::<DataBaseEvent>(&value) {
+                if event.event.time < time {
+                    for index_key in index::index_keys(&event.event) {
+                        self.index_tree
+                            .remove(index_key.as_bytes())
+                            .map_err(resp_error::database_error)?;
+                    }
+                    self.message_tree
+                        .remove(&key)
+                        .map_err(resp_error::database_error)?;
+                    count += 1;
+                }
+            }
+            last = Some(key.to_vec());
+        }
+        Ok((count, last.filter(|_| scanned >= limit)))
+    }
+    fn remove_image(&self, key: &[u8]) -> Result<(), RespError> {
+        self.image_tree
+            .remove(key)
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn remove_voice(&self, key: &[u8]) -> Result<(), RespError> {
+        self.audio_tree
+            .remove(key)
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn remove_file(&self, key: &[u8]) -> Result<(), RespError> {
+        self.file_tree
+            .remove(key)
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn remove_video(&self, key: &[u8]) -> Result<(), RespError> {
+        self.video_tree
+            .remove(key)
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn flush(&self) -> Result<(), RespError> {
+        // trees share one pagecache, flushing one of them persists all
+        self.message_tree
+            .flush()
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(DumpRecord) -> Result<(), RespError>,
+    ) -> Result<(), RespError> {
+        for entry in self.message_tree.iter() {
+            let (key, value) = entry.map_err(resp_error::database_error)?;
+            match rmp_serde::from_slice(&value) {
+                Ok(event) => f(DumpRecord::message(event))?,
+                Err(e) => tracing::warn!(
+                    target: crate::WALLE_Q,
+                    "skip corrupt message {}: {}",
+                    String::from_utf8_lossy(&key),
+                    e
+                ),
+            }
+        }
+        for entry in self.image_tree.iter() {
+            let (_, value) = entry.map_err(resp_error::database_error)?;
+            f(DumpRecord::image(&value))?;
+        }
+        for entry in self.audio_tree.iter() {
+            let (_, value) = entry.map_err(resp_error::database_error)?;
+            f(DumpRecord::voice(&value))?;
+        }
+        for entry in self.video_tree.iter() {
+            let (_, value) = entry.map_err(resp_error::database_error)?;
+            f(DumpRecord::video(&value))?;
+        }
+        for entry in self.file_tree.iter() {
+            let (key, value) = entry.map_err(resp_error::database_error)?;
+            match rmp_serde::from_slice(&value) {
+                Ok(file) => f(DumpRecord::File(file))?,
+                Err(e) => tracing::warn!(
+                    target: crate::WALLE_Q,
+                    "skip corrupt file {}: {}",
+                    hex::encode(&key),
+                    e
+                ),
+            }
+        }
+        Ok(())
     }
 }
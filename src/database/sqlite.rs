@@ -0,0 +1,533 @@
+use crate::error;
+
+use super::*;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::sync::MutexGuard;
+use walle_core::{resp::resp_error, util::ValueMapExt};
+
+const INIT_SQL: &str = "
+CREATE TABLE IF NOT EXISTS message (
+    message_id  TEXT PRIMARY KEY,
+    detail_type TEXT NOT NULL,
+    group_id    TEXT,
+    user_id     TEXT NOT NULL,
+    time        REAL NOT NULL,
+    alt_message TEXT NOT NULL,
+    seqs        TEXT NOT NULL,
+    rands       TEXT NOT NULL,
+    event       TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS message_time ON message (time);
+CREATE TABLE IF NOT EXISTS message_index (
+    key        TEXT PRIMARY KEY,
+    message_id TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS image (
+    image_id  TEXT PRIMARY KEY,
+    file_name TEXT NOT NULL,
+    size      INTEGER NOT NULL,
+    url       TEXT,
+    data      BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS voice (
+    voice_id TEXT PRIMARY KEY,
+    size     INTEGER NOT NULL,
+    data     BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS video (
+    video_id TEXT PRIMARY KEY,
+    name     TEXT NOT NULL,
+    size     INTEGER NOT NULL,
+    data     BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS meta (
+    key   TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS file (
+    file_id TEXT PRIMARY KEY,
+    name    TEXT NOT NULL,
+    mime    TEXT NOT NULL,
+    size    INTEGER NOT NULL
+);
+";
+
+/// single file sqlite database, tables can be inspected with any sqlite client
+///
+/// `event` column is the json encoded event, `seqs` and `rands` are json arrays
+pub(crate) struct SqliteDb(std::sync::Mutex<Connection>);
+
+impl DatabaseInit for SqliteDb {
+    fn init(base_path: &str) -> Self {
+        let conn = Connection::open(format!("{}/{}", base_path, "walle-q.db")).unwrap();
+        conn.execute_batch(INIT_SQL).unwrap();
+        Self(std::sync::Mutex::new(conn))
+    }
+}
+
+impl SqliteDb {
+    /// connection inside a write transaction, writes between two `flush` are committed
+    /// together instead of one transaction per statement
+    fn writer(&self) -> Result<MutexGuard<Connection>, RespError> {
+        let conn = self.0.lock().unwrap();
+        if conn.is_autocommit() {
+            conn.execute_batch("BEGIN")
+                .map_err(resp_error::database_error)?;
+        }
+        Ok(conn)
+    }
+}
+
+fn join_i32(v: &[i32]) -> String {
+    serde_json::to_string(v).unwrap()
+}
+
+fn split_i32(s: &str) -> Result<Vec<i32>, RespError> {
+    serde_json::from_str(s).map_err(resp_error::database_error)
+}
+
+fn file_from_row(row: &rusqlite::Row) -> rusqlite::Result<StoredFile> {
+    Ok(StoredFile {
+        sha256: row.get(0)?,
+        name: row.get(1)?,
+        mime: row.get(2)?,
+        size: row.get(3)?,
+    })
+}
+
+fn insert_index(conn: &Connection, event: &Event) -> Result<(), RespError> {
+    for key in index::index_keys(event) {
+        conn.execute(
+            "INSERT OR REPLACE INTO message_index (key, message_id) VALUES (?1, ?2)",
+            params![key, event.message_id()],
+        )
+        .map_err(resp_error::database_error)?;
+    }
+    Ok(())
+}
+
+impl Database for SqliteDb {
+    fn get_message(&self, key: &str) -> Result<Option<DataBaseEvent>, RespError> {
+        self.0
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT event, seqs, rands FROM message WHERE message_id = ?1",
+                params![key],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(resp_error::database_error)?
+            .map(|(event, seqs, rands)| {
+                Ok(DataBaseEvent {
+                    event: serde_json::from_str(&event).map_err(resp_error::database_error)?,
+                    seqs: split_i32(&seqs)?,
+                    rands: split_i32(&rands)?,
+                })
+            })
+            .transpose()
+    }
+
+    fn insert_message(
+        &self,
+        value: &Event,
+        seqs: Vec<i32>,
+        rands: Vec<i32>,
+    ) -> Result<(), RespError> {
+        let get = |key: &str| value.extra.get_downcast::<String>(key).ok();
+        let event = serde_json::to_string(value).map_err(resp_error::database_error)?;
+        let conn = self.writer()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO message
+                 (message_id, detail_type, group_id, user_id, time, alt_message, seqs, rands, event)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                value.message_id(),
+                value.detail_type,
+                get("group_id"),
+                get("user_id").unwrap_or_default(),
+                value.time,
+                get("alt_message").unwrap_or_default(),
+                join_i32(&seqs),
+                join_i32(&rands),
+                event,
+            ],
+        )
+        .map_err(resp_error::database_error)?;
+        insert_index(&conn, value)
+    }
+
+    fn get_image<T>(&self, key: &[u8]) -> Result<Option<T>, RespError>
+    where
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        self.0
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT data FROM image WHERE image_id = ?1",
+                params![hex::encode(key)],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(resp_error::database_error)?
+            .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
+            .transpose()
+    }
+
+    fn insert_image<T>(&self, value: &T) -> Result<(), RespError>
+    where
+        T: serde::Serialize + SImage,
+    {
+        let data = rmp_serde::to_vec(value).map_err(resp_error::database_error)?;
+        self.writer()?
+            .execute(
+                "INSERT OR REPLACE INTO image (image_id, file_name, size, url, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    value.hex_image_id(),
+                    value.get_file_name(),
+                    value.get_size(),
+                    value.get_url(),
+                    data,
+                ],
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn get_voice<T: SVoice>(&self, key: &[u8]) -> Result<Option<T>, RespError> {
+        self.0
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT data FROM voice WHERE voice_id = ?1",
+                params![hex::encode(key)],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(resp_error::database_error)?
+            .map(|v| SVoice::from_data(&v).ok_or_else(|| error::file_type_not_match("")))
+            .transpose()
+    }
+
+    fn insert_voice<T: SVoice>(&self, value: &T) -> Result<(), RespError> {
+        self.writer()?
+            .execute(
+                "INSERT OR REPLACE INTO voice (voice_id, size, data) VALUES (?1, ?2, ?3)",
+                params![value.hex_voice_id(), value.get_size(), value.to_data()],
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn get_file(&self, key: &[u8]) -> Result<Option<StoredFile>, RespError> {
+        self.0
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT file_id, name, mime, size FROM file WHERE file_id = ?1",
+                params![hex::encode(key)],
+                file_from_row,
+            )
+            .optional()
+            .map_err(resp_error::database_error)
+    }
+
+    fn insert_file(&self, value: &StoredFile) -> Result<(), RespError> {
+        self.writer()?
+            .execute(
+                "INSERT OR REPLACE INTO file (file_id, name, mime, size) VALUES (?1, ?2, ?3, ?4)",
+                params![value.sha256, value.name, value.mime, value.size],
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn get_video(&self, key: &[u8]) -> Result<Option<Videos>, RespError> {
+        self.0
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT data FROM video WHERE video_id = ?1",
+                params![hex::encode(key)],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(resp_error::database_error)?
+            .map(|v| rmp_serde::from_slice(&v).map_err(|e| error::file_type_not_match(e)))
+            .transpose()
+    }
+
+    fn insert_video(&self, value: &Videos) -> Result<(), RespError> {
+        let data = rmp_serde::to_vec(value).map_err(resp_error::database_error)?;
+        self.writer()?
+            .execute(
+                "INSERT OR REPLACE INTO video (video_id, name, size, data) VALUES (?1, ?2, ?3, ?4)",
+                params![value.hex_video_id(), value.name(), value.get_size(), data],
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn scan_index(&self, start: &str, end: &str) -> Result<Vec<String>, RespError> {
+        let conn = self.0.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT message_id FROM message_index WHERE key >= ?1 AND key < ?2 ORDER BY key",
+            )
+            .map_err(resp_error::database_error)?;
+        let ids = stmt
+            .query_map(params![start, end], |row| row.get::<_, String>(0))
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(resp_error::database_error);
+        ids
+    }
+
+    fn put_index(&self, key: &str, value: &str) -> Result<(), RespError> {
+        self.writer()?
+            .execute(
+                "INSERT OR REPLACE INTO message_index (key, message_id) VALUES (?1, ?2)",
+                params![key, value],
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn remove_index(&self, key: &str) -> Result<(), RespError> {
+        self.writer()?
+            .execute("DELETE FROM message_index WHERE key = ?1", params![key])
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn migrate_message_ids(&self) -> Result<usize, RespError> {
+        let conn = self.writer()?;
+        let migrated = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'migrated_message_ids'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map_err(resp_error::database_error)?;
+        if migrated.is_some() {
+            return Ok(0);
+        }
+        let legacy = conn
+            .prepare("SELECT message_id, event, seqs, rands FROM message WHERE message_id NOT LIKE '%:%'")
+            .and_then(|mut stmt| {
+                let rows = stmt
+                    .query_map([], |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, String>(3)?,
+                        ))
+                    })?
+                    .collect::<Result<Vec<_>, _>>();
+                rows
+            })
+            .map_err(resp_error::database_error)?;
+        let mut count = 0;
+        for (old, event, seqs, rands) in legacy {
+            let (Ok(event), Ok(seqs), Ok(rands)) = (
+                serde_json::from_str(&event),
+                split_i32(&seqs),
+                split_i32(&rands),
+            ) else {
+                continue;
+            };
+            let event = DataBaseEvent { event, seqs, rands };
+            if let Some((message_id, event)) = migrate_legacy_message(&old, event) {
+                conn.execute(
+                    "UPDATE message SET message_id = ?1, event = ?2 WHERE message_id = ?3",
+                    params![
+                        message_id,
+                        serde_json::to_string(&event.event).map_err(resp_error::database_error)?,
+                        old
+                    ],
+                )
+                .map_err(resp_error::database_error)?;
+                insert_index(&conn, &event.event)?;
+                count += 1;
+            }
+        }
+        conn.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('migrated_message_ids', '1')",
+            [],
+        )
+        .map_err(resp_error::database_error)?;
+        Ok(count)
+    }
+
+    fn remove_messages_before(
+        &self,
+        time: f64,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<(usize, Option<Vec<u8>>), RespError> {
+        // message ids are ascii, a lossy cursor from another backend keeps the byte order
+        let after = after.map_or_else(String::new, |after| {
+            String::from_utf8_lossy(after).into_owned()
+        });
+        let conn = self.writer()?;
+        let rows = conn
+            .prepare(
+                "SELECT message_id, time FROM message WHERE message_id > ?1
+                 ORDER BY message_id LIMIT ?2",
+            )
+            .and_then(|mut stmt| {
+                let rows = stmt
+                    .query_map(params![after, limit as i64], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>();
+                rows
+            })
+            .map_err(resp_error::database_error)?;
+        let mut count = 0;
+        for (message_id, _) in rows.iter().filter(|(_, t)| *t < time) {
+            conn.execute(
+                "DELETE FROM message_index WHERE message_id = ?1",
+                params![message_id],
+            )
+            .and_then(|_| {
+                conn.execute(
+                    "DELETE FROM message WHERE message_id = ?1",
+                    params![message_id],
+                )
+            })
+            .map_err(resp_error::database_error)?;
+            count += 1;
+        }
+        let next = rows
+            .last()
+            .filter(|_| rows.len() >= limit)
+            .map(|(message_id, _)| message_id.clone().into_bytes());
+        Ok((count, next))
+    }
+    fn remove_image(&self, key: &[u8]) -> Result<(), RespError> {
+        self.writer()?
+            .execute(
+                "DELETE FROM image WHERE image_id = ?1",
+                params![hex::encode(key)],
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn remove_voice(&self, key: &[u8]) -> Result<(), RespError> {
+        self.writer()?
+            .execute(
+                "DELETE FROM voice WHERE voice_id = ?1",
+                params![hex::encode(key)],
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn remove_video(&self, key: &[u8]) -> Result<(), RespError> {
+        self.writer()?
+            .execute(
+                "DELETE FROM video WHERE video_id = ?1",
+                params![hex::encode(key)],
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn remove_file(&self, key: &[u8]) -> Result<(), RespError> {
+        self.writer()?
+            .execute(
+                "DELETE FROM file WHERE file_id = ?1",
+                params![hex::encode(key)],
+            )
+            .map_err(resp_error::database_error)?;
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), RespError> {
+        let conn = self.0.lock().unwrap();
+        if !conn.is_autocommit() {
+            conn.execute_batch("COMMIT")
+                .map_err(resp_error::database_error)?;
+        }
+        Ok(())
+    }
+
+    fn for_each_record(
+        &self,
+        f: &mut dyn FnMut(DumpRecord) -> Result<(), RespError>,
+    ) -> Result<(), RespError> {
+        let conn = self.0.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT message_id, event, seqs, rands FROM message")
+            .map_err(resp_error::database_error)?;
+        let mut rows = stmt.query([]).map_err(resp_error::database_error)?;
+        while let Some(row) = rows.next().map_err(resp_error::database_error)? {
+            let message_id: String = row.get(0).map_err(resp_error::database_error)?;
+            let event: String = row.get(1).map_err(resp_error::database_error)?;
+            let seqs: String = row.get(2).map_err(resp_error::database_error)?;
+            let rands: String = row.get(3).map_err(resp_error::database_error)?;
+            let event = serde_json::from_str(&event)
+                .map_err(resp_error::database_error)
+                .and_then(|event| {
+                    Ok(DataBaseEvent {
+                        event,
+                        seqs: split_i32(&seqs)?,
+                        rands: split_i32(&rands)?,
+                    })
+                });
+            match event {
+                Ok(event) => f(DumpRecord::message(event))?,
+                Err(e) => tracing::warn!(
+                    target: crate::WALLE_Q,
+                    "skip corrupt message {}: {}",
+                    message_id,
+                    e.message
+                ),
+            }
+        }
+        for (sql, record) in [
+            (
+                "SELECT data FROM image",
+                DumpRecord::image as fn(&[u8]) -> DumpRecord,
+            ),
+            ("SELECT data FROM voice", DumpRecord::voice),
+            ("SELECT data FROM video", DumpRecord::video),
+        ] {
+            let mut stmt = conn.prepare(sql).map_err(resp_error::database_error)?;
+            let mut rows = stmt.query([]).map_err(resp_error::database_error)?;
+            while let Some(row) = rows.next().map_err(resp_error::database_error)? {
+                let data: Vec<u8> = row.get(0).map_err(resp_error::database_error)?;
+                f(record(&data))?;
+            }
+        }
+        let mut stmt = conn
+            .prepare("SELECT file_id, name, mime, size FROM file")
+            .map_err(resp_error::database_error)?;
+        let files = stmt
+            .query_map([], file_from_row)
+            .map_err(resp_error::database_error)?;
+        for file in files {
+            f(DumpRecord::File(file.map_err(resp_error::database_error)?))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_seqs_round_trip() {
+    let seqs = vec![-5, 3, i32::MIN, i32::MAX, 0];
+    assert_eq!(split_i32(&join_i32(&seqs)).unwrap(), seqs);
+    assert_eq!(split_i32(&join_i32(&[])).unwrap(), Vec::<i32>::new());
+    assert!(split_i32("-5-3").is_err());
+}
@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use ricq::msg::elem::VideoFile;
+use serde::{Deserialize, Serialize};
+use walle_core::resp::RespError;
+use walle_core::structs::FileId;
+
+use crate::error;
+
+pub async fn save_video(data: &[u8], name: &str, base_path: &str) -> Result<Videos, RespError> {
+    let video = Videos::Local(LocalVideo {
+        name: name.to_owned(),
+        md5: md5::compute(data).0.to_vec(),
+        size: data.len() as u32,
+    });
+    tokio::fs::write(video.path(base_path), data)
+        .await
+        .map_err(error::file_write_error)?;
+    Ok(video)
+}
+
+/// uploaded by `upload_file`, the content is in `VIDEO_DIR`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalVideo {
+    pub name: String,
+    pub md5: Vec<u8>,
+    pub size: u32,
+}
+
+/// short video received in a message, can be sent again as is
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceivedVideo {
+    pub name: String,
+    pub uuid: Vec<u8>,
+    pub size: i32,
+    pub thumb_size: i32,
+    pub md5: Vec<u8>,
+    pub thumb_md5: Vec<u8>,
+}
+
+impl From<VideoFile> for ReceivedVideo {
+    fn from(video: VideoFile) -> Self {
+        Self {
+            name: video.name,
+            uuid: video.uuid,
+            size: video.size,
+            thumb_size: video.thumb_size,
+            md5: video.md5,
+            thumb_md5: video.thumb_md5,
+        }
+    }
+}
+
+impl From<ReceivedVideo> for VideoFile {
+    fn from(video: ReceivedVideo) -> Self {
+        Self {
+            name: video.name,
+            uuid: video.uuid,
+            size: video.size,
+            thumb_size: video.thumb_size,
+            md5: video.md5,
+            thumb_md5: video.thumb_md5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Videos {
+    Local(LocalVideo),
+    Received(ReceivedVideo),
+}
+
+impl Videos {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Local(v) => &v.name,
+            Self::Received(v) => &v.name,
+        }
+    }
+
+    pub fn get_size(&self) -> u32 {
+        match self {
+            Self::Local(v) => v.size,
+            Self::Received(v) => v.size as u32,
+        }
+    }
+
+    /// md5 with size, same as voices
+    pub fn video_id(&self) -> Vec<u8> {
+        let md5 = match self {
+            Self::Local(v) => &v.md5,
+            Self::Received(v) => &v.md5,
+        };
+        [md5.as_slice(), self.get_size().to_be_bytes().as_slice()].concat()
+    }
+
+    pub fn hex_video_id(&self) -> String {
+        hex::encode(self.video_id())
+    }
+
+    pub fn as_file_id_content(&self) -> FileId {
+        FileId {
+            file_id: self.hex_video_id(),
+        }
+    }
+
+    /// local file of the video, received videos are downloaded to it on first use
+    pub fn path(&self, base_path: &str) -> PathBuf {
+        let mut path = PathBuf::from(format!("{}/{}", base_path, crate::VIDEO_DIR));
+        path.push(self.hex_video_id());
+        path
+    }
+}
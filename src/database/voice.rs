@@ -38,6 +38,12 @@ pub trait SVoice: Sized {
             file_id: self.hex_voice_id(),
         }
     }
+    /// local file of the voice, received voices are only there once downloaded
+    fn path(&self, base_path: &str) -> PathBuf {
+        let mut path = PathBuf::from(format!("{}/{}", base_path, crate::VOICE_DIR));
+        path.push(self.hex_voice_id());
+        path
+    }
 }
 
 impl SVoice for Ptt {
@@ -76,17 +82,54 @@ impl SVoice for LocalVoice {
     }
 }
 
-impl LocalVoice {
-    pub fn path(&self, base_path: &str) -> PathBuf {
-        let mut path = PathBuf::from(format!("{}/{}", base_path, crate::VOICE_DIR));
-        path.push(self.hex_voice_id());
-        path
+/// voice received in a message, kept with where it came from to query its download url
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceivedVoice {
+    pub ptt: Ptt,
+    pub group_code: Option<i64>,
+    pub sender: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReceivedVoiceData {
+    group_code: Option<i64>,
+    sender: i64,
+    ptt: Vec<u8>,
+}
+
+// not a valid protobuf field tag, so a `Ptt` never starts with it
+const RECEIVED_VOICE_MAGIC: &[u8] = b"WQRV";
+
+impl SVoice for ReceivedVoice {
+    fn get_md5(&self) -> &[u8] {
+        self.ptt.get_md5()
+    }
+    fn get_size(&self) -> u32 {
+        self.ptt.get_size()
+    }
+    fn to_data(&self) -> Vec<u8> {
+        let data = ReceivedVoiceData {
+            group_code: self.group_code,
+            sender: self.sender,
+            ptt: self.ptt.to_data(),
+        };
+        [RECEIVED_VOICE_MAGIC, &rmp_serde::to_vec(&data).unwrap()].concat()
+    }
+    fn from_data(data: &Vec<u8>) -> Option<Self> {
+        let data: ReceivedVoiceData =
+            rmp_serde::from_slice(data.strip_prefix(RECEIVED_VOICE_MAGIC)?).ok()?;
+        Some(Self {
+            ptt: Ptt::from_data(&data.ptt)?,
+            group_code: data.group_code,
+            sender: data.sender,
+        })
     }
 }
 
 pub enum Voices {
     Local(LocalVoice),
     Ptt(Ptt),
+    Received(ReceivedVoice),
 }
 
 impl SVoice for Voices {
@@ -94,22 +137,27 @@ impl SVoice for Voices {
         match self {
             Voices::Local(v) => v.get_md5(),
             Voices::Ptt(v) => v.get_md5(),
+            Voices::Received(v) => v.get_md5(),
         }
     }
     fn get_size(&self) -> u32 {
         match self {
             Voices::Local(v) => v.get_size(),
             Voices::Ptt(v) => v.get_size(),
+            Voices::Received(v) => v.get_size(),
         }
     }
     fn to_data(&self) -> Vec<u8> {
         match self {
             Voices::Local(v) => v.to_data(),
             Voices::Ptt(v) => v.to_data(),
+            Voices::Received(v) => v.to_data(),
         }
     }
     fn from_data(data: &Vec<u8>) -> Option<Self> {
-        if let Some(v) = Ptt::from_data(data) {
+        if let Some(v) = ReceivedVoice::from_data(data) {
+            Some(Voices::Received(v))
+        } else if let Some(v) = Ptt::from_data(data) {
             Some(Voices::Ptt(v))
         } else if let Some(v) = LocalVoice::from_data(data) {
             Some(Voices::Local(v))
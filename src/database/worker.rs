@@ -0,0 +1,267 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+use walle_core::{
+    event::Event,
+    resp::{resp_error, RespError},
+};
+
+use super::{
+    DataBaseEvent, Database, HistoryQuery, RecallState, SImage, SVoice, SearchQuery, Storage,
+    StoredFile, Videos,
+};
+use crate::WALLE_Q;
+
+/// flush backends at most once per interval while writes keep coming
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+/// jobs applied in one burst before the flush timer is checked
+const MAX_BATCH: usize = 256;
+
+type Task = Box<dyn FnOnce(&Storage) + Send>;
+
+enum Job {
+    Task { task: Task, write: bool },
+    Flush(oneshot::Sender<Result<(), RespError>>),
+}
+
+fn worker_stopped() -> RespError {
+    resp_error::database_error("database worker stopped")
+}
+
+/// completion of a queued write, dropping it does not cancel the write
+pub(crate) struct WriteAck(oneshot::Receiver<Result<(), RespError>>);
+
+impl WriteAck {
+    pub async fn wait(self) -> Result<(), RespError> {
+        self.0.await.unwrap_or_else(|_| Err(worker_stopped()))
+    }
+}
+
+/// handle of the storage worker thread
+///
+/// all backends are owned by one dedicated thread, jobs run in queue order so a read
+/// always sees the writes queued before it. writes are applied in bursts and flushed
+/// on a timer and on `flush`, async callers never block on disk I/O.
+pub struct WQDatabase {
+    tx: Mutex<mpsc::Sender<Job>>,
+    enabled: bool,
+}
+
+impl WQDatabase {
+    pub(crate) fn spawn(storage: Storage) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let enabled = storage.not_empty();
+        std::thread::Builder::new()
+            .name("walle-q-db".to_owned())
+            .spawn(move || run(storage, rx))
+            .unwrap();
+        Self {
+            tx: Mutex::new(tx),
+            enabled,
+        }
+    }
+
+    pub fn not_empty(&self) -> bool {
+        self.enabled
+    }
+
+    fn submit<T, F>(&self, write: bool, f: F) -> oneshot::Receiver<Result<T, RespError>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Storage) -> Result<T, RespError> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let task: Task = Box::new(move |storage| {
+            tx.send(f(storage)).ok();
+        });
+        // a stopped worker drops the job and the receiver reports it
+        self.tx.lock().unwrap().send(Job::Task { task, write }).ok();
+        rx
+    }
+
+    /// run `f` on the worker after every job queued before it
+    pub(crate) async fn read<T, F>(&self, f: F) -> Result<T, RespError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Storage) -> Result<T, RespError> + Send + 'static,
+    {
+        self.submit(false, f)
+            .await
+            .unwrap_or_else(|_| Err(worker_stopped()))
+    }
+
+    /// like `read`, but the changes made by `f` are flushed with the next batch
+    pub(crate) async fn modify<T, F>(&self, f: F) -> Result<T, RespError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Storage) -> Result<T, RespError> + Send + 'static,
+    {
+        self.submit(true, f)
+            .await
+            .unwrap_or_else(|_| Err(worker_stopped()))
+    }
+
+    /// queue a write without waiting, failures are logged by the worker
+    pub(crate) fn write<F>(&self, f: F) -> WriteAck
+    where
+        F: FnOnce(&Storage) -> Result<(), RespError> + Send + 'static,
+    {
+        WriteAck(self.submit(true, move |storage| {
+            let result = f(storage);
+            if let Err(e) = &result {
+                warn!(target: WALLE_Q, "database write failed: {}", e.message);
+            }
+            result
+        }))
+    }
+
+    /// wait until every queued write is applied and persisted
+    pub async fn flush(&self) -> Result<(), RespError> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.lock().unwrap().send(Job::Flush(tx)).ok();
+        rx.await.unwrap_or_else(|_| Err(worker_stopped()))
+    }
+
+    pub(crate) async fn get_message(
+        &self,
+        message_id: &str,
+    ) -> Result<Option<DataBaseEvent>, RespError> {
+        let message_id = message_id.to_owned();
+        self.read(move |s| s.get_message(&message_id)).await
+    }
+
+    pub(crate) fn insert_message(
+        &self,
+        value: &Event,
+        seqs: Vec<i32>,
+        rands: Vec<i32>,
+    ) -> WriteAck {
+        let value = value.clone();
+        self.write(move |s| s.insert_message(&value, seqs, rands))
+    }
+
+    pub(crate) fn mark_recalled(&self, message_id: String, state: RecallState) -> WriteAck {
+        self.write(move |s| {
+            if !s.mark_recalled(&message_id, &state)? {
+                debug!(target: WALLE_Q, "recalled message {} not stored", message_id);
+            }
+            Ok(())
+        })
+    }
+
+    pub(crate) async fn get_image<T>(&self, key: &[u8]) -> Result<Option<T>, RespError>
+    where
+        T: for<'de> serde::Deserialize<'de> + Send + 'static,
+    {
+        let key = key.to_vec();
+        self.read(move |s| s.get_image(&key)).await
+    }
+
+    pub(crate) fn insert_image<T>(&self, value: &T) -> WriteAck
+    where
+        T: serde::Serialize + SImage + Clone + Send + 'static,
+    {
+        let value = value.clone();
+        self.write(move |s| s.insert_image(&value))
+    }
+
+    pub(crate) async fn get_voice<T>(&self, key: &[u8]) -> Result<Option<T>, RespError>
+    where
+        T: SVoice + Send + 'static,
+    {
+        let key = key.to_vec();
+        self.read(move |s| s.get_voice(&key)).await
+    }
+
+    pub(crate) fn insert_voice<T>(&self, value: &T) -> WriteAck
+    where
+        T: SVoice + Clone + Send + 'static,
+    {
+        let value = value.clone();
+        self.write(move |s| s.insert_voice(&value))
+    }
+
+    pub(crate) async fn get_file(&self, key: &[u8]) -> Result<Option<StoredFile>, RespError> {
+        let key = key.to_vec();
+        self.read(move |s| s.get_file(&key)).await
+    }
+
+    pub(crate) fn insert_file(&self, value: &StoredFile) -> WriteAck {
+        let value = value.clone();
+        self.write(move |s| s.insert_file(&value))
+    }
+
+    pub(crate) async fn get_video(&self, key: &[u8]) -> Result<Option<Videos>, RespError> {
+        let key = key.to_vec();
+        self.read(move |s| s.get_video(&key)).await
+    }
+
+    pub(crate) fn insert_video(&self, value: &Videos) -> WriteAck {
+        let value = value.clone();
+        self.write(move |s| s.insert_video(&value))
+    }
+
+    pub(crate) async fn get_message_history(
+        &self,
+        query: HistoryQuery,
+    ) -> Result<Vec<DataBaseEvent>, RespError> {
+        self.read(move |s| s.get_message_history(&query)).await
+    }
+
+    pub(crate) async fn search_messages(
+        &self,
+        query: SearchQuery,
+    ) -> Result<Vec<DataBaseEvent>, RespError> {
+        self.read(move |s| s.search_messages(&query)).await
+    }
+}
+
+fn flush_storage(storage: &Storage) {
+    if let Err(e) = storage.flush() {
+        warn!(target: WALLE_Q, "database flush failed: {}", e.message);
+    }
+}
+
+fn run(storage: Storage, rx: mpsc::Receiver<Job>) {
+    let mut dirty = false;
+    let mut last_flush = Instant::now();
+    loop {
+        let job = match rx.recv_timeout(FLUSH_INTERVAL) {
+            Ok(job) => job,
+            Err(RecvTimeoutError::Timeout) => {
+                if dirty {
+                    flush_storage(&storage);
+                    dirty = false;
+                    last_flush = Instant::now();
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        for job in std::iter::once(job).chain(rx.try_iter().take(MAX_BATCH - 1)) {
+            match job {
+                Job::Task { task, write } => {
+                    task(&storage);
+                    // read repair writes from read jobs too
+                    dirty |= write | storage.take_repaired();
+                }
+                Job::Flush(tx) => {
+                    tx.send(storage.flush()).ok();
+                    dirty = false;
+                    last_flush = Instant::now();
+                }
+            }
+        }
+        if dirty && last_flush.elapsed() >= FLUSH_INTERVAL {
+            flush_storage(&storage);
+            dirty = false;
+            last_flush = Instant::now();
+        }
+    }
+    if dirty {
+        flush_storage(&storage);
+    }
+}
@@ -14,10 +14,15 @@ error_type!(file_create_error, 32003, "文件创建失败");
 error_type!(file_write_error, 32004, "文件写入失败");
 error_type!(file_not_found, 32005, "文件不存在");
 error_type!(file_type_not_match, 32006, "文件类型不匹配");
+error_type!(file_range_error, 32007, "文件范围错误");
+error_type!(path_not_allowed, 32008, "路径不允许访问");
 error_type!(net_download_fail, 33001, "网络下载失败");
+error_type!(url_not_allowed, 33002, "URL不允许访问");
+error_type!(download_too_large, 33003, "下载内容过大");
 error_type!(rq_error, 34001, "ricq错误");
 error_type!(login_failed, 34002, "login failed");
 error_type!(risk_controlled, 34003, "可能被风控");
+error_type!(group_file_error, 34004, "群文件操作失败");
 error_type!(message_not_exist, 35001, "消息不存在");
 error_type!(friend_not_exist, 35002, "好友不存在");
 error_type!(group_not_exist, 35003, "群不存在");
@@ -29,6 +34,12 @@ error_type!(bad_image_path, 61003, "图片路径错误");
 error_type!(bad_image_data, 61004, "图片内容错误");
 error_type!(audio_encode_failed, 61005, "音频编码失败");
 error_type!(silk_encode_failed, 61005, "silk编码失败");
+error_type!(bad_voice_url, 61006, "语音URL错误");
+error_type!(bad_voice_path, 61007, "语音路径错误");
+error_type!(bad_voice_data, 61008, "语音内容错误");
+error_type!(bad_video_path, 61009, "视频路径错误");
+error_type!(bad_video_data, 61010, "视频内容错误");
+error_type!(bad_video_url, 61011, "视频URL错误");
 
 pub fn map_action_parse_error(error: WalleError) -> RespError {
     match error {
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use hyper::body::HttpBody;
+use hyper::client::connect::dns::Name;
+use hyper::client::HttpConnector;
+use hyper::header::LOCATION;
+use hyper::service::Service;
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use url::{Host, Url};
+use walle_core::resp::RespError;
+
+use crate::config::FetchConfig;
+use crate::error;
+
+impl FetchConfig {
+    /// download a caller supplied `uri` if the policy allows it
+    pub(crate) async fn get(
+        &self,
+        uri: &str,
+        headers: HashMap<String, String>,
+    ) -> Result<Vec<u8>, RespError> {
+        self.check_url(uri).await?;
+        let fetch = async {
+            if is_http(uri) {
+                self.get_http(uri, &headers).await
+            } else {
+                uri_reader::uget_with_headers(uri, headers)
+                    .await
+                    .map_err(|e| error::net_download_fail(e))
+            }
+        };
+        let data = tokio::time::timeout(Duration::from_secs(self.timeout), fetch)
+            .await
+            .map_err(|_| error::net_download_fail(format!("timeout after {}s", self.timeout)))??;
+        // http bodies are capped while streaming, file and base64 data are local
+        self.check_size(data.len(), uri)?;
+        Ok(data)
+    }
+
+    /// follows up to `MAX_REDIRECTS` redirects, each target is checked like the original url.
+    /// caller headers are only sent to the original host
+    async fn get_http(
+        &self,
+        uri: &str,
+        headers: &HashMap<String, String>,
+    ) -> Result<Vec<u8>, RespError> {
+        let client = self.client();
+        let origin = Url::parse(uri).map_err(|e| error::bad_param(format!("url: {}", e)))?;
+        let mut url = origin.clone();
+        for _ in 0..=MAX_REDIRECTS {
+            let mut req = Request::get(url.as_str());
+            if url.host() == origin.host() {
+                for (key, value) in headers {
+                    req = req.header(key, value);
+                }
+            }
+            let req = req
+                .body(Body::empty())
+                .map_err(|e| error::bad_param(format!("headers: {}", e)))?;
+            let resp = client
+                .request(req)
+                .await
+                .map_err(|e| error::net_download_fail(e))?;
+            let status = resp.status();
+            if status.is_redirection() {
+                let location = resp
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .ok_or_else(|| {
+                        error::net_download_fail(format!("{} without location", status))
+                    })?;
+                url = url
+                    .join(location)
+                    .map_err(|e| error::net_download_fail(e))?;
+                if !is_http(url.as_str()) {
+                    return Err(error::url_not_allowed(format!("redirect to {}", url)));
+                }
+                self.check_url(url.as_str()).await?;
+                continue;
+            }
+            if !status.is_success() {
+                return Err(error::net_download_fail(format!("{} from {}", status, url)));
+            }
+            if let Some(len) = resp.body().size_hint().exact() {
+                self.check_size(len as usize, url.as_str())?;
+            }
+            let mut body = resp.into_body();
+            let mut data = vec![];
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk.map_err(|e| error::net_download_fail(e))?;
+                data.extend_from_slice(&chunk);
+                self.check_size(data.len(), url.as_str())?;
+            }
+            return Ok(data);
+        }
+        Err(error::net_download_fail(format!(
+            "more than {} redirects from {}",
+            MAX_REDIRECTS, uri
+        )))
+    }
+
+    /// redirects are not followed by the client itself, every connection resolves through
+    /// `CheckedResolver`
+    fn client(&self) -> Client<HttpsConnector<HttpConnector<CheckedResolver>>> {
+        let mut http = HttpConnector::new_with_resolver(CheckedResolver {
+            block_private: self.block_private,
+            allow_hosts: Arc::new(self.allow_hosts.clone()),
+        });
+        http.enforce_http(false);
+        Client::builder().build(HttpsConnector::new_with_connector(http))
+    }
+
+    fn check_size(&self, size: usize, uri: &str) -> Result<(), RespError> {
+        match self.max_download_mb {
+            Some(max_mb) if size as u64 > max_mb * 1024 * 1024 => Err(error::download_too_large(
+                format!("{} bytes from {}", size, uri),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// canonical form of a caller supplied `path`, which must be inside `path_root` if set
+    pub(crate) async fn check_path(&self, path: impl AsRef<Path>) -> Result<PathBuf, RespError> {
+        let path = tokio::fs::canonicalize(path)
+            .await
+            .map_err(error::file_open_error)?;
+        if let Some(root) = &self.path_root {
+            let root = tokio::fs::canonicalize(root)
+                .await
+                .map_err(error::file_open_error)?;
+            if !path.starts_with(&root) {
+                return Err(error::path_not_allowed(path.display()));
+            }
+        }
+        Ok(path)
+    }
+
+    async fn check_url(&self, uri: &str) -> Result<(), RespError> {
+        let Some((scheme, _)) = uri.split_once("://") else {
+            return Err(error::bad_param(format!("url: {}", uri)));
+        };
+        let scheme = scheme.to_ascii_lowercase();
+        if !self.schemes.iter().any(|s| s.eq_ignore_ascii_case(&scheme)) {
+            return Err(error::url_not_allowed(format!("scheme {}", scheme)));
+        }
+        match scheme.as_str() {
+            "http" | "https" => {}
+            "file" => {
+                let path = Url::parse(uri)
+                    .ok()
+                    .and_then(|url| url.to_file_path().ok())
+                    .ok_or_else(|| error::bad_param(format!("url: {}", uri)))?;
+                return self.check_path(path).await.map(|_| ());
+            }
+            // no host to check, e.g. base64
+            _ => return Ok(()),
+        }
+        let url = Url::parse(uri).map_err(|e| error::bad_param(format!("url: {}", e)))?;
+        let (host, addrs): (String, Vec<IpAddr>) = match url.host() {
+            Some(Host::Domain(domain)) => (domain.to_ascii_lowercase(), vec![]),
+            Some(Host::Ipv4(ip)) => (ip.to_string(), vec![ip.into()]),
+            Some(Host::Ipv6(ip)) => (ip.to_string(), vec![ip.into()]),
+            None => return Err(error::bad_param(format!("url: {}", uri))),
+        };
+        let allowed = match_host(&self.allow_hosts, &host);
+        if match_host(&self.deny_hosts, &host) || (!self.allow_hosts.is_empty() && !allowed) {
+            return Err(error::url_not_allowed(host));
+        }
+        // domains are checked by `CheckedResolver` on every connection
+        if self.block_private && !allowed {
+            if let Some(ip) = addrs.into_iter().find(is_private) {
+                return Err(error::url_not_allowed(format!(
+                    "{} resolves to {}",
+                    host, ip
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+const MAX_REDIRECTS: usize = 5;
+
+fn is_http(uri: &str) -> bool {
+    uri.split_once("://").map_or(false, |(scheme, _)| {
+        scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+    })
+}
+
+/// resolves the hosts the download client connects to, a host resolving to a private
+/// address is refused unless allowed, so the checked address is the connected one
+#[derive(Clone)]
+struct CheckedResolver {
+    block_private: bool,
+    allow_hosts: Arc<Vec<String>>,
+}
+
+impl Service<Name> for CheckedResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_ascii_lowercase();
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if resolver.block_private && !match_host(&resolver.allow_hosts, &host) {
+                if let Some(addr) = addrs.iter().find(|addr| is_private(&addr.ip())) {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::PermissionDenied,
+                        format!("{} resolves to {}", host, addr.ip()),
+                    ));
+                }
+            }
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+/// `host` is one of `hosts` or a subdomain of one
+fn match_host(hosts: &[String], host: &str) -> bool {
+    hosts.iter().any(|h| {
+        let h = h.to_ascii_lowercase();
+        host == h || host.ends_with(&format!(".{}", h))
+    })
+}
+
+/// loopback, private, link-local and other addresses not routed on the internet
+fn is_private(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // shared address space 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_private(&IpAddr::V4(ip));
+            }
+            let segment = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // unique local fc00::/7 and link-local fe80::/10
+                || segment & 0xfe00 == 0xfc00
+                || segment & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+#[test]
+fn test_fetch_host() {
+    assert!(is_private(&"127.0.0.1".parse().unwrap()));
+    assert!(is_private(&"100.100.0.1".parse().unwrap()));
+    assert!(is_private(&"::ffff:192.168.1.1".parse().unwrap()));
+    assert!(is_private(&"fd00::1".parse().unwrap()));
+    assert!(!is_private(&"1.1.1.1".parse().unwrap()));
+    assert!(!is_private(&"2606:4700::1111".parse().unwrap()));
+
+    let hosts = vec!["Example.com".to_string()];
+    assert!(match_host(&hosts, "example.com"));
+    assert!(match_host(&hosts, "cdn.example.com"));
+    assert!(!match_host(&hosts, "badexample.com"));
+}
@@ -1,31 +1,39 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use cached::Cached;
+use ricq::structs::{FriendAudio, GroupAudio};
+use serde::{Deserialize, Serialize};
 use sha2::Digest;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt, SeekFrom};
+use tokio::sync::{Mutex, MutexGuard};
 use tokio::{fs::File, io::AsyncReadExt};
-use walle_core::action::{GetFileFragmented, UploadFileFragmented};
+use walle_core::action::GetFileFragmented;
 use walle_core::resp::{Resp, RespError};
 use walle_core::structs::FileId;
-use walle_core::util::OneBotBytes;
+use walle_core::util::{OneBotBytes, Value};
 use walle_core::value;
 
-use crate::database::{save_image, save_voice, Database, Images, SImage, SVoice};
+use crate::database::{
+    save_file, save_image, save_video, save_voice, Images, ReceivedVideo, ReceivedVoice, SImage,
+    SVoice, StoredFile, Videos, Voices,
+};
 use crate::error;
-use crate::model::{WQGetFile, WQUploadFile};
+use crate::model::{
+    GetMissingFragments, UploadedFile, WQGetFile, WQUploadFile, WQUploadFileFragmented,
+};
 
 use super::RespResult;
 
 impl super::Handler {
-    async fn get_file_data(c: WQUploadFile) -> Result<Vec<u8>, RespError> {
+    async fn get_file_data(&self, c: WQUploadFile) -> Result<Vec<u8>, RespError> {
         match c.ty.as_str() {
             "url" if let Some(url) = c.url => {
-                uri_reader::uget_with_headers(&url, c.headers.unwrap_or_default())
-                    .await
-                    .map_err(|e|error::net_download_fail(e))
+                self.fetch.get(&url, c.headers.unwrap_or_default()).await
             }
             "path" if let Some(path) = c.path => {
-                let input_path = PathBuf::from(path);
+                let input_path = self.fetch.check_path(path).await?;
                 let mut file =  File::open(&input_path).await.map_err(|e| {
                     error::file_open_error(e)
                 })?;
@@ -40,40 +48,74 @@ impl super::Handler {
         }
     }
 
-    pub async fn upload_file(&self, c: WQUploadFile) -> RespResult<FileId> {
+    pub async fn upload_file(&self, c: WQUploadFile) -> RespResult<UploadedFile> {
         let file_type = c.file_type.clone().unwrap_or("image".to_string());
-        let data = Self::get_file_data(c).await?;
-        match file_type.as_str() {
+        let (name, mime, sha256) = (c.name.clone(), c.mime.clone(), c.sha256.clone());
+        let data = self.get_file_data(c).await?;
+        let calc_sha = sha2::Sha256::digest(&data).to_vec();
+        check_sha256(sha256, &calc_sha)?;
+        let size = data.len() as i64;
+        let file_id = match file_type.as_str() {
             "image" => self.upload_image(data).await,
             "voice" => self.upload_voice(data).await,
+            "video" => self.upload_video(data, &name).await,
+            "file" => self.upload_other(data, &name, mime).await,
             ty => Err(error::unsupported_param(ty)),
-        }
+        }?;
+        Ok(UploadedFile {
+            file_id: file_id.file_id,
+            sha256: hex::encode(calc_sha),
+            size,
+        })
     }
 
     pub async fn upload_image(&self, data: Vec<u8>) -> RespResult<FileId> {
         let info = save_image(&data, &self.data_path).await?;
-        self.database.insert_image(&info);
+        self.database.insert_image(&info).wait().await?;
         Ok(info.as_file_id_content())
     }
 
     pub async fn upload_voice(&self, data: Vec<u8>) -> RespResult<FileId> {
         let local = save_voice(&data, &self.data_path).await?;
-        self.database.insert_voice(&local);
+        self.database.insert_voice(&local).wait().await?;
         Ok(local.as_file_id_content())
     }
 
+    pub async fn upload_video(&self, data: Vec<u8>, name: &str) -> RespResult<FileId> {
+        let video = save_video(&data, name, &self.data_path).await?;
+        self.database.insert_video(&video).wait().await?;
+        Ok(video.as_file_id_content())
+    }
+
+    /// content addressed, the file_id is the hex sha256 of the content
+    pub async fn upload_other(
+        &self,
+        data: Vec<u8>,
+        name: &str,
+        mime: Option<String>,
+    ) -> RespResult<FileId> {
+        let file = save_file(&data, name, mime, &self.data_path).await?;
+        self.database.insert_file(&file).wait().await?;
+        Ok(file.as_file_id_content())
+    }
+
     pub async fn get_file(&self, c: WQGetFile) -> RespResult<WQUploadFile> {
         let file_type = c.file_type.clone().unwrap_or("image".to_string());
         match file_type.as_str() {
             "image" => self.get_image(&c).await,
+            "voice" => self.get_voice(&c).await,
+            "video" => self.get_video(&c).await,
+            "file" => self.get_other(&c).await,
             ty => Err(error::unsupported_param(ty)),
         }
     }
 
     pub async fn get_image(&self, c: &WQGetFile) -> RespResult<WQUploadFile> {
-        if let Some(image) = self.database.get_image::<Images>(
-            &hex::decode(&c.file_id).map_err(|_| error::bad_param("file_id"))?,
-        )? {
+        if let Some(image) = self
+            .database
+            .get_image::<Images>(&hex::decode(&c.file_id).map_err(|_| error::bad_param("file_id"))?)
+            .await?
+        {
             match c.ty.as_str() {
                 "url" => {
                     if let Some(url) = image.get_url() {
@@ -86,6 +128,7 @@ impl super::Handler {
                             data: None,
                             sha256: None,
                             file_type: Some("image".to_string()),
+                            mime: None,
                         })
                     } else {
                         Err(error::bad_image_url(image.get_file_name()))
@@ -102,6 +145,7 @@ impl super::Handler {
                             data: None,
                             sha256: None,
                             file_type: Some("image".to_string()),
+                            mime: None,
                         })
                     } else {
                         Err(error::bad_image_path(image.get_file_name()))
@@ -120,6 +164,7 @@ impl super::Handler {
                             headers: None,
                             sha256: Some(hex::encode(c.finalize())),
                             file_type: Some("image".to_string()),
+                            mime: None,
                         })
                     } else {
                         Err(error::bad_image_data(image.get_file_name()))
@@ -132,134 +177,435 @@ impl super::Handler {
         }
     }
 
-    pub async fn upload_file_fragmented(
-        &self,
-        c: UploadFileFragmented,
-    ) -> RespResult<Option<FileId>> {
-        match c {
-            UploadFileFragmented::Prepare { name, total_size } => {
+    pub async fn get_voice(&self, c: &WQGetFile) -> RespResult<WQUploadFile> {
+        let voice = self
+            .database
+            .get_voice::<Voices>(&hex::decode(&c.file_id).map_err(|_| error::bad_param("file_id"))?)
+            .await?
+            .ok_or_else(|| error::file_not_found(&c.file_id))?;
+        let name = voice.hex_voice_id();
+        let file = |ty: &str| WQUploadFile {
+            ty: ty.to_string(),
+            name: name.clone(),
+            url: None,
+            headers: None,
+            path: None,
+            data: None,
+            sha256: None,
+            file_type: Some("voice".to_string()),
+            mime: None,
+        };
+        match c.ty.as_str() {
+            "url" => match &voice {
+                Voices::Received(received) => Ok(WQUploadFile {
+                    url: Some(self.voice_url(received).await?),
+                    ..file("url")
+                }),
+                _ => Err(error::bad_voice_url(&name)),
+            },
+            "path" => {
+                let path = self.voice_file(&voice).await?;
+                Ok(WQUploadFile {
+                    path: Some(path.to_str().unwrap().to_string()),
+                    ..file("path")
+                })
+            }
+            "data" => {
+                let path = self.voice_file(&voice).await?;
+                let data = tokio::fs::read(&path)
+                    .await
+                    .map_err(|_| error::bad_voice_data(&name))?;
+                let mut c = sha2::Sha256::default();
+                c.update(&data);
+                Ok(WQUploadFile {
+                    data: Some(data.into()),
+                    sha256: Some(hex::encode(c.finalize())),
+                    ..file("data")
+                })
+            }
+            ty => Err(error::unsupported_param(ty)),
+        }
+    }
+
+    /// received videos are downloaded on first use, only they have a url
+    pub async fn get_video(&self, c: &WQGetFile) -> RespResult<WQUploadFile> {
+        let video = self.stored_video(&c.file_id).await?;
+        let upload = |ty: &str| WQUploadFile {
+            ty: ty.to_string(),
+            name: video.name().to_string(),
+            url: None,
+            headers: None,
+            path: None,
+            data: None,
+            sha256: None,
+            file_type: Some("video".to_string()),
+            mime: None,
+        };
+        match c.ty.as_str() {
+            "url" => match &video {
+                Videos::Received(received) => Ok(WQUploadFile {
+                    url: Some(self.video_url(received).await?),
+                    ..upload("url")
+                }),
+                _ => Err(error::bad_video_url(video.hex_video_id())),
+            },
+            "path" => {
+                let path = self.video_file(&video).await?;
+                Ok(WQUploadFile {
+                    path: Some(path.to_str().unwrap().to_string()),
+                    ..upload("path")
+                })
+            }
+            "data" => {
+                let path = self.video_file(&video).await?;
+                let data = tokio::fs::read(&path)
+                    .await
+                    .map_err(|_| error::bad_video_data(video.hex_video_id()))?;
+                let mut c = sha2::Sha256::default();
+                c.update(&data);
+                Ok(WQUploadFile {
+                    data: Some(data.into()),
+                    sha256: Some(hex::encode(c.finalize())),
+                    ..upload("data")
+                })
+            }
+            ty => Err(error::unsupported_param(ty)),
+        }
+    }
+
+    async fn stored_video(&self, file_id: &str) -> RespResult<Videos> {
+        let id = hex::decode(file_id).map_err(|_| error::bad_param("file_id"))?;
+        self.database
+            .get_video(&id)
+            .await?
+            .ok_or_else(|| error::file_not_found(file_id))
+    }
+
+    /// download url of a received short video
+    async fn video_url(&self, video: &ReceivedVideo) -> RespResult<String> {
+        let uin = self.get_client()?.uin().await;
+        let req = pb::ShortVideoReqBody {
+            cmd: Some(400),
+            seq: Some(0),
+            download_req: Some(pb::ShortVideoDownloadReq {
+                from_uin: Some(uin),
+                to_uin: Some(uin),
+                chat_type: Some(1),
+                client_type: Some(7),
+                file_id: Some(String::from_utf8_lossy(&video.uuid).into_owned()),
+                group_code: Some(1),
+                file_md5: Some(video.md5.clone()),
+                business_type: Some(1),
+                file_type: Some(2),
+                down_type: Some(2),
+                scene_type: Some(2),
+            }),
+        };
+        let rsp = self
+            .uni_request::<pb::ShortVideoRspBody>("PttCenterSvc.ShortVideoDownReq", req)
+            .await?
+            .download_rsp
+            .unwrap_or_default();
+        let addr = rsp.download_addr.unwrap_or_default();
+        match addr.host.first() {
+            Some(host) if rsp.ret_code == 0 => Ok(format!("{}{}", host, addr.url_args)),
+            _ => Err(error::bad_video_url(format!(
+                "{} {}",
+                rsp.ret_code, rsp.ret_msg
+            ))),
+        }
+    }
+
+    /// local file of a video, a received video is downloaded to it on first use
+    async fn video_file(&self, video: &Videos) -> RespResult<PathBuf> {
+        let path = video.path(&self.data_path);
+        if path.exists() {
+            return Ok(path);
+        }
+        let Videos::Received(received) = video else {
+            return Err(error::bad_video_path(video.hex_video_id()));
+        };
+        let url = self.video_url(received).await?;
+        let data = uri_reader::uget_with_headers(&url, Default::default())
+            .await
+            .map_err(|e| error::net_download_fail(e))?;
+        tokio::fs::write(&path, &data)
+            .await
+            .map_err(error::file_write_error)?;
+        Ok(path)
+    }
+
+    pub async fn get_other(&self, c: &WQGetFile) -> RespResult<WQUploadFile> {
+        let file = self.stored_file(&c.file_id).await?;
+        let path = file.path(&self.data_path);
+        let upload = |ty: &str| WQUploadFile {
+            ty: ty.to_string(),
+            name: file.name.clone(),
+            url: None,
+            headers: None,
+            path: None,
+            data: None,
+            sha256: Some(file.sha256.clone()),
+            file_type: Some("file".to_string()),
+            mime: Some(file.mime.clone()),
+        };
+        match c.ty.as_str() {
+            "path" => Ok(WQUploadFile {
+                path: Some(path.to_str().unwrap().to_string()),
+                ..upload("path")
+            }),
+            "data" => {
+                let data = tokio::fs::read(&path)
+                    .await
+                    .map_err(error::file_read_error)?;
+                Ok(WQUploadFile {
+                    data: Some(data.into()),
+                    ..upload("data")
+                })
+            }
+            ty => Err(error::unsupported_param(ty)),
+        }
+    }
+
+    /// record of a generic file whose content is still in `FILE_DIR`
+    pub(super) async fn stored_file(&self, file_id: &str) -> RespResult<StoredFile> {
+        let id = hex::decode(file_id).map_err(|_| error::bad_param("file_id"))?;
+        self.database
+            .get_file(&id)
+            .await?
+            .filter(|file| file.path(&self.data_path).exists())
+            .ok_or_else(|| error::file_not_found(file_id))
+    }
+
+    async fn voice_url(&self, voice: &ReceivedVoice) -> RespResult<String> {
+        let cli = self.get_client()?;
+        match voice.group_code {
+            Some(group_code) => {
+                cli.get_group_audio_url(group_code, GroupAudio(voice.ptt.clone()))
+                    .await
+            }
+            None => {
+                cli.get_friend_audio_url(voice.sender, FriendAudio(voice.ptt.clone()))
+                    .await
+            }
+        }
+        .map_err(|e| error::bad_voice_url(e))
+    }
+
+    /// local file of a voice, a received voice is downloaded to it on first use
+    async fn voice_file(&self, voice: &Voices) -> RespResult<PathBuf> {
+        let path = voice.path(&self.data_path);
+        if path.exists() {
+            return Ok(path);
+        }
+        let Voices::Received(received) = voice else {
+            return Err(error::bad_voice_path(voice.hex_voice_id()));
+        };
+        let url = self.voice_url(received).await?;
+        let data = uri_reader::uget_with_headers(&url, Default::default())
+            .await
+            .map_err(|e| error::net_download_fail(e))?;
+        tokio::fs::write(&path, &data)
+            .await
+            .map_err(error::file_write_error)?;
+        Ok(path)
+    }
+
+    pub async fn upload_file_fragmented(&self, c: WQUploadFileFragmented) -> RespResult<Resp> {
+        match c.stage.as_str() {
+            "prepare" => {
+                let (Some(name), Some(total_size)) = (c.name, c.total_size) else {
+                    return Err(error::bad_param("name or total_size"));
+                };
+                if total_size <= 0 || total_size > MAX_FRAGMENT_TOTAL_SIZE {
+                    return Err(error::bad_param(format!(
+                        "total_size {} out of (0, {}]",
+                        total_size, MAX_FRAGMENT_TOTAL_SIZE
+                    )));
+                }
+                let file_type = c.file_type.unwrap_or("image".to_string());
+                if !matches!(file_type.as_str(), "image" | "voice" | "video" | "file") {
+                    return Err(error::unsupported_param(file_type));
+                }
                 let file_id = format!("{}-{}", name, total_size);
-                self.uploading_fragment.lock().await.cache_set(
-                    file_id.clone(),
-                    FragmentFile {
-                        total_size,
-                        files: vec![],
-                    },
-                );
-                Ok(Some(FileId { file_id }))
+                // preparing an unfinished upload again resumes it
+                if let Ok(uploading) = self.uploading(&file_id).await {
+                    if uploading[&file_id].file_type == file_type {
+                        return Ok(FileId { file_id }.into());
+                    }
+                }
+                let fragment = FragmentFile {
+                    name,
+                    total_size,
+                    file_type,
+                    files: vec![],
+                };
+                let mut uploading = self.uploading_fragment.lock().await;
+                // data left by an expired or different upload of the same file_id
+                tokio::fs::remove_file(fragment_path(&self.data_path, &file_id, "part"))
+                    .await
+                    .ok();
+                self.save_fragment(&file_id, &fragment).await?;
+                uploading.insert(file_id.clone(), fragment);
+                Ok(FileId { file_id }.into())
             }
-            UploadFileFragmented::Transfer {
-                file_id,
-                offset,
-                data,
-            } => {
-                let mut file_path =
-                    std::path::PathBuf::from(format!("{}/{}", &self.data_path, crate::CACHE_DIR));
-                file_path.push(format!("{}-{}", file_id, offset));
-                let mut file = tokio::fs::File::create(file_path)
+            "transfer" => {
+                let (Some(file_id), Some(offset), Some(data)) = (c.file_id, c.offset, c.data)
+                else {
+                    return Err(error::bad_param("file_id, offset or data"));
+                };
+                let size = data.0.len() as i64;
+                let total_size = self.uploading(&file_id).await?[&file_id].total_size;
+                if offset < 0 || offset > total_size - size {
+                    return Err(error::file_range_error(format!(
+                        "offset {} size {} of {}",
+                        offset, size, total_size
+                    )));
+                }
+                let mut file = tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .open(fragment_path(&self.data_path, &file_id, "part"))
                     .await
                     .map_err(error::file_create_error)?;
+                file.seek(SeekFrom::Start(offset as u64))
+                    .await
+                    .map_err(error::file_write_error)?;
                 file.write_all(&data.0)
                     .await
                     .map_err(error::file_write_error)?;
-                let size = data.0.len() as i64;
-                match self.uploading_fragment.lock().await.cache_get_mut(&file_id) {
-                    Some(f) => f.files.push((offset, size)),
-                    None => return Err(error::prepare_file_first(&file_id)),
-                }
-                Ok(None)
+                file.sync_data().await.map_err(error::file_write_error)?;
+                let mut uploading = self.uploading(&file_id).await?;
+                let fragment = uploading.get_mut(&file_id).unwrap();
+                fragment.files.push((offset, size));
+                self.save_fragment(&file_id, fragment).await?;
+                Ok(Value::Null.into())
             }
-            UploadFileFragmented::Finish { file_id, sha256 } => {
-                let mut fragment = self
-                    .uploading_fragment
-                    .lock()
-                    .await
-                    .cache_remove(&file_id)
-                    .ok_or_else(|| error::prepare_file_first(&file_id))?;
-                fragment.files.sort();
-                let mut data = Vec::with_capacity(fragment.total_size as usize);
-                let mut total_size = 0;
-                for (offset, size) in fragment.files {
-                    let mut file_path = std::path::PathBuf::from(format!(
-                        "{}/{}",
-                        &self.data_path,
-                        crate::CACHE_DIR
-                    ));
-                    file_path.push(format!("{}-{}", file_id, offset));
-                    let mut file = tokio::fs::File::open(&file_path)
-                        .await
-                        .map_err(error::file_open_error)?;
-                    file.read_buf(&mut data)
-                        .await
-                        .map_err(error::file_read_error)?;
-                    drop(file);
-                    tokio::fs::remove_file(file_path).await.ok();
-                    total_size += size;
-                }
-                if total_size != fragment.total_size {
+            "finish" => {
+                let Some(file_id) = c.file_id else {
+                    return Err(error::bad_param("file_id"));
+                };
+                let mut uploading = self.uploading(&file_id).await?;
+                if let Some((offset, size)) = uploading[&file_id].missing().first() {
                     return Err(error::file_total_size_not_match(format!(
-                        "get {} of {}",
-                        total_size, fragment.total_size
+                        "missing {} bytes from {}",
+                        size, offset
                     )));
                 }
-                let mut calc_sha = sha2::Sha256::default();
-                calc_sha.update(&data);
-                let calc_sha = calc_sha.finalize().to_vec();
-                if let Some(sha) = sha256 {
-                    let sha = hex::decode(sha).map_err(|_| error::bad_param("sha256"))?;
-                    if calc_sha != sha {
-                        return Err(error::file_sha256_not_match(format!(
-                            "get {} of {}",
-                            hex::encode(calc_sha),
-                            hex::encode(sha)
-                        )));
-                    }
-                }
-                self.upload_image(data).await.map(|id| Some(id))
+                let fragment = uploading.remove(&file_id).unwrap();
+                drop(uploading);
+                tokio::fs::remove_file(fragment_path(&self.data_path, &file_id, "json"))
+                    .await
+                    .ok();
+                let part = fragment_path(&self.data_path, &file_id, "part");
+                let result = self.finish_fragment(&part, fragment, c.sha256).await;
+                tokio::fs::remove_file(part).await.ok();
+                result.map(Into::into)
             }
+            ty => Err(error::unsupported_param(ty)),
         }
     }
 
-    pub async fn get_file_fragmented(&self, c: GetFileFragmented) -> RespResult<Resp> {
-        use ricq::structs::ImageInfo;
-        use tokio::io::{AsyncSeekExt, SeekFrom};
-        async fn to_info(
-            h: &super::Handler,
-            simage: impl SImage,
-        ) -> Result<(ImageInfo, String), RespError> {
-            let data = simage.data(&h.data_path).await.map_err(error::rq_error)?;
-            let sha256 = {
-                let mut s = sha2::Sha256::default();
-                s.update(&data);
-                hex::encode(&s.finalize())
-            };
-            let info = save_image(&data, &h.data_path).await?;
-            h.database.insert_image(&info);
-            Ok((info, sha256))
+    pub async fn get_missing_fragments(&self, c: GetMissingFragments) -> RespResult<Resp> {
+        let uploading = self.uploading(&c.file_id).await?;
+        let fragment = &uploading[&c.file_id];
+        let missing: Vec<Value> = fragment
+            .missing()
+            .into_iter()
+            .map(|(offset, size)| value!({ "offset": offset, "size": size }))
+            .collect();
+        Ok(value!({
+            "total_size": fragment.total_size,
+            "missing": missing
+        })
+        .into())
+    }
+
+    /// upload states with `file_id` loaded, states are read from disk after a restart
+    async fn uploading(
+        &self,
+        file_id: &str,
+    ) -> RespResult<MutexGuard<'_, HashMap<String, FragmentFile>>> {
+        let mut uploading = self.uploading_fragment.lock().await;
+        if !uploading.contains_key(file_id) {
+            let fragment = tokio::fs::read(fragment_path(&self.data_path, file_id, "json"))
+                .await
+                .ok()
+                .and_then(|data| serde_json::from_slice(&data).ok())
+                .ok_or_else(|| error::prepare_file_first(file_id))?;
+            uploading.insert(file_id.to_owned(), fragment);
         }
+        Ok(uploading)
+    }
+
+    async fn save_fragment(&self, file_id: &str, fragment: &FragmentFile) -> RespResult<()> {
+        let data = serde_json::to_vec(fragment).map_err(error::file_write_error)?;
+        tokio::fs::write(fragment_path(&self.data_path, file_id, "json"), data)
+            .await
+            .map_err(error::file_write_error)
+    }
+
+    /// check the assembled file and store it by its file type
+    async fn finish_fragment(
+        &self,
+        part: &Path,
+        fragment: FragmentFile,
+        sha256: Option<String>,
+    ) -> RespResult<UploadedFile> {
+        let mut file = File::open(part).await.map_err(error::file_open_error)?;
+        let total_size = file.metadata().await.map_err(error::file_read_error)?.len() as i64;
+        if total_size != fragment.total_size {
+            return Err(error::file_total_size_not_match(format!(
+                "get {} of {}",
+                total_size, fragment.total_size
+            )));
+        }
+        let (calc_sha, _) = hash_file(&mut file).await?;
+        drop(file);
+        check_sha256(sha256, &calc_sha)?;
+        let file_id = match fragment.file_type.as_str() {
+            "file" => {
+                let file = StoredFile::new(
+                    hex::encode(&calc_sha),
+                    &fragment.name,
+                    None,
+                    total_size as u64,
+                );
+                tokio::fs::rename(part, file.path(&self.data_path))
+                    .await
+                    .map_err(error::file_write_error)?;
+                self.database.insert_file(&file).wait().await?;
+                Ok(file.as_file_id_content())
+            }
+            // images, voices and short videos are small, they are handled in memory
+            ty => {
+                let data = tokio::fs::read(part)
+                    .await
+                    .map_err(error::file_read_error)?;
+                match ty {
+                    "voice" => self.upload_voice(data).await,
+                    "video" => self.upload_video(data, &fragment.name).await,
+                    _ => self.upload_image(data).await,
+                }
+            }
+        }?;
+        Ok(UploadedFile {
+            file_id: file_id.file_id,
+            sha256: hex::encode(calc_sha),
+            size: total_size,
+        })
+    }
+
+    pub async fn get_file_fragmented(&self, c: GetFileFragmented) -> RespResult<Resp> {
         match c {
             GetFileFragmented::Prepare { file_id } => {
-                let (info, sha256) = match self
-                    .database
-                    .get_image(&hex::decode(&file_id).map_err(|_| error::bad_param("file_id"))?)?
-                    .ok_or_else(|| error::file_not_found(&file_id))?
-                {
-                    Images::Friend(f) => to_info(self, f).await?,
-                    Images::Group(g) => to_info(self, g).await?,
-                    Images::Info(i) => {
-                        let data = i.data(&self.data_path).await.map_err(error::rq_error)?;
-                        let sha256 = {
-                            let mut s = sha2::Sha256::default();
-                            s.update(&data);
-                            hex::encode(&s.finalize())
-                        };
-                        (i, sha256)
-                    }
-                };
+                let (name, path) = self.media_file(&file_id).await?;
+                let mut file = File::open(&path).await.map_err(error::file_open_error)?;
+                let (sha256, total_size) = hash_file(&mut file).await?;
                 Ok(value!({
-                    "name": info.filename,
-                    "total_size": info.size,
-                    "sha256": sha256
+                    "name": name,
+                    "total_size": total_size as i64,
+                    "sha256": hex::encode(sha256)
                 })
                 .into())
             }
@@ -268,26 +614,259 @@ impl super::Handler {
                 offset,
                 size,
             } => {
-                let info: ImageInfo = self
-                    .database
-                    .get_image(&hex::decode(&file_id).map_err(|_| error::bad_param("file_id"))?)?
-                    .ok_or_else(|| error::file_not_found(&file_id))?;
-                let mut file = tokio::fs::File::open(info.path(&self.data_path))
+                let (_, path) = self.media_file(&file_id).await?;
+                let mut file = tokio::fs::File::open(path)
                     .await
                     .map_err(error::file_open_error)?;
+                let total_size =
+                    file.metadata().await.map_err(error::file_read_error)?.len() as i64;
+                if offset < 0 || offset >= total_size || size <= 0 {
+                    return Err(error::file_range_error(format!(
+                        "offset {} size {} of {}",
+                        offset, size, total_size
+                    )));
+                }
                 file.seek(SeekFrom::Start(offset as u64))
                     .await
                     .map_err(error::file_read_error)?;
-                let mut data = Vec::with_capacity(size as usize);
-                file.read(&mut data).await.map_err(error::file_read_error)?;
+                // the last fragment may be shorter than requested
+                let mut data = vec![0; size.min(total_size - offset) as usize];
+                file.read_exact(&mut data)
+                    .await
+                    .map_err(error::file_read_error)?;
                 let data = OneBotBytes(data);
                 Ok(value!({ "data": data }).into())
             }
         }
     }
+
+    /// name and local file of a stored image, voice, video or file, remote media are downloaded first
+    async fn media_file(&self, file_id: &str) -> RespResult<(String, PathBuf)> {
+        let id = hex::decode(file_id).map_err(|_| error::bad_param("file_id"))?;
+        // images and voices may share one key space, a voice record fails to decode as image
+        if let Ok(Some(image)) = self.database.get_image::<Images>(&id).await {
+            let path = image.path(&self.data_path);
+            if !path.exists() {
+                let data = image
+                    .data(&self.data_path)
+                    .await
+                    .map_err(|e| error::bad_image_data(e))?;
+                tokio::fs::write(&path, &data)
+                    .await
+                    .map_err(error::file_write_error)?;
+            }
+            return Ok((image.get_file_name().to_owned(), path));
+        }
+        if let Some(voice) = self.database.get_voice::<Voices>(&id).await? {
+            return Ok((voice.hex_voice_id(), self.voice_file(&voice).await?));
+        }
+        if let Some(video) = self.database.get_video(&id).await? {
+            return Ok((video.name().to_owned(), self.video_file(&video).await?));
+        }
+        let file = self.stored_file(file_id).await?;
+        Ok((file.name.clone(), file.path(&self.data_path)))
+    }
 }
 
+/// state of a fragmented upload, kept in `UPLOAD_DIR` until `finish`
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FragmentFile {
+    pub name: String,
     pub total_size: i64,
+    pub file_type: String,
+    /// received `(offset, size)`, may overlap
     pub files: Vec<(i64, i64)>,
 }
+
+impl FragmentFile {
+    /// `(offset, size)` of the ranges not received yet
+    pub fn missing(&self) -> Vec<(i64, i64)> {
+        let mut files = self.files.clone();
+        files.sort();
+        let mut missing = vec![];
+        let mut end = 0;
+        for (offset, size) in files {
+            if offset > end {
+                missing.push((end, offset - end));
+            }
+            end = end.max(offset + size);
+        }
+        if end < self.total_size {
+            missing.push((end, self.total_size - end));
+        }
+        missing
+    }
+}
+
+/// sha256 and size of `file` read in chunks, large files are never held in memory
+async fn hash_file(file: &mut File) -> RespResult<(Vec<u8>, u64)> {
+    let mut sha = sha2::Sha256::default();
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = file.read(&mut buf).await.map_err(error::file_read_error)?;
+        if n == 0 {
+            break;
+        }
+        sha.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((sha.finalize().to_vec(), size))
+}
+
+/// `expected` is the hex sha256 given by the client, skipped if not given
+fn check_sha256(expected: Option<String>, calc_sha: &[u8]) -> RespResult<()> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let sha = hex::decode(&expected).map_err(|_| error::bad_param("sha256"))?;
+    if calc_sha != sha {
+        return Err(error::file_sha256_not_match(format!(
+            "get {} of {}",
+            hex::encode(calc_sha),
+            expected
+        )));
+    }
+    Ok(())
+}
+
+/// unfinished fragmented uploads not touched for this long are removed
+const FRAGMENT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const FRAGMENT_CLEAN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// the largest file a fragmented upload takes
+const MAX_FRAGMENT_TOTAL_SIZE: i64 = 4 * 1024 * 1024 * 1024;
+
+/// removes expired fragmented uploads at once and then every `FRAGMENT_CLEAN_INTERVAL`
+pub(crate) fn spawn_fragment_cleaner(
+    data_path: Arc<String>,
+    uploading: Arc<Mutex<HashMap<String, FragmentFile>>>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FRAGMENT_CLEAN_INTERVAL);
+        loop {
+            interval.tick().await;
+            let removed = remove_expired_fragments(&data_path, &uploading).await;
+            if removed > 0 {
+                tracing::info!(
+                    target: crate::WALLE_Q,
+                    "removed {} expired upload files",
+                    removed
+                );
+            }
+        }
+    });
+}
+
+/// `.json` states and `.part` data are rewritten by every transfer, their mtime is the
+/// last activity. the lock keeps transfers out while files are removed
+async fn remove_expired_fragments(
+    data_path: &str,
+    uploading: &Mutex<HashMap<String, FragmentFile>>,
+) -> usize {
+    let mut uploading = uploading.lock().await;
+    let dir = format!("{}/{}", data_path, crate::UPLOAD_DIR);
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return 0;
+    };
+    let mut removed = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let expired = entry
+            .metadata()
+            .await
+            .ok()
+            .and_then(|meta| meta.modified().ok())
+            .and_then(|modified| modified.elapsed().ok())
+            .map_or(false, |age| age > FRAGMENT_TTL);
+        if expired && tokio::fs::remove_file(entry.path()).await.is_ok() {
+            removed += 1;
+        }
+    }
+    // cached states of removed uploads, `uploading` reloads the others from disk
+    uploading.retain(|file_id, _| fragment_path(data_path, file_id, "json").exists());
+    removed
+}
+
+/// `file_id` contains the file name, files are named by its md5
+fn fragment_path(data_path: &str, file_id: &str, ext: &str) -> PathBuf {
+    PathBuf::from(format!(
+        "{}/{}/{:x}.{}",
+        data_path,
+        crate::UPLOAD_DIR,
+        md5::compute(file_id),
+        ext
+    ))
+}
+
+/// short video download request of PttCenterSvc, unused fields are left out
+mod pb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ShortVideoReqBody {
+        #[prost(int32, optional, tag = "1")]
+        pub cmd: Option<i32>,
+        #[prost(int32, optional, tag = "2")]
+        pub seq: Option<i32>,
+        #[prost(message, optional, tag = "4")]
+        pub download_req: Option<ShortVideoDownloadReq>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ShortVideoDownloadReq {
+        #[prost(int64, optional, tag = "1")]
+        pub from_uin: Option<i64>,
+        #[prost(int64, optional, tag = "2")]
+        pub to_uin: Option<i64>,
+        #[prost(int32, optional, tag = "3")]
+        pub chat_type: Option<i32>,
+        #[prost(int32, optional, tag = "4")]
+        pub client_type: Option<i32>,
+        #[prost(string, optional, tag = "5")]
+        pub file_id: Option<String>,
+        #[prost(int64, optional, tag = "6")]
+        pub group_code: Option<i64>,
+        #[prost(bytes = "vec", optional, tag = "8")]
+        pub file_md5: Option<Vec<u8>>,
+        #[prost(int32, optional, tag = "9")]
+        pub business_type: Option<i32>,
+        #[prost(int32, optional, tag = "10")]
+        pub file_type: Option<i32>,
+        #[prost(int32, optional, tag = "11")]
+        pub down_type: Option<i32>,
+        #[prost(int32, optional, tag = "12")]
+        pub scene_type: Option<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ShortVideoRspBody {
+        #[prost(message, optional, tag = "4")]
+        pub download_rsp: Option<ShortVideoDownloadRsp>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ShortVideoDownloadRsp {
+        #[prost(int32, tag = "1")]
+        pub ret_code: i32,
+        #[prost(string, tag = "2")]
+        pub ret_msg: String,
+        #[prost(message, optional, tag = "9")]
+        pub download_addr: Option<ShortVideoAddr>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ShortVideoAddr {
+        #[prost(string, repeated, tag = "10")]
+        pub host: Vec<String>,
+        #[prost(string, tag = "11")]
+        pub url_args: String,
+    }
+}
+
+#[test]
+fn test_fragment_missing() {
+    let fragment = FragmentFile {
+        name: "test".to_string(),
+        total_size: 100,
+        file_type: "file".to_string(),
+        files: vec![(60, 20), (0, 10), (5, 15)],
+    };
+    assert_eq!(fragment.missing(), vec![(20, 40), (80, 20)]);
+}
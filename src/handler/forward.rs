@@ -0,0 +1,273 @@
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use prost::Message;
+use ricq::msg::MessageChain;
+use ricq::structs::ForwardMessage;
+use ricq_core::highway::BdhInput;
+use ricq_core::pb::msg as msg_pb;
+
+use crate::error;
+use crate::parse::{forward_chain, forward_preview};
+
+use super::RespResult;
+
+/// highway command of merged forward uploads
+const UPLOAD_COMMAND: i32 = 27;
+/// item holding the outermost nodes of an upload
+const MAIN_ITEM: &str = "MultiMsg";
+
+impl super::Handler {
+    /// upload `forwards` for the friend `user_id` and reference them with a service 35 xml,
+    /// ricq only uploads merged forwards within a group
+    pub(super) async fn upload_friend_forward(
+        &self,
+        user_id: i64,
+        forwards: Vec<ForwardMessage>,
+    ) -> RespResult<MessageChain> {
+        let (count, preview) = (forwards.len(), forward_preview(&forwards));
+        let mut items = vec![];
+        let msg = pack_nodes(user_id, forwards, &mut items);
+        items.push(pb::PbMultiMsgItem {
+            file_name: Some(MAIN_ITEM.to_owned()),
+            buffer: Some(pb::PbMultiMsgNew { msg: msg.clone() }),
+        });
+        let transmit = pb::PbMultiMsgTransmit {
+            msg,
+            pb_item_list: items,
+        };
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder
+            .write_all(&transmit.encode_to_vec())
+            .map_err(error::rq_error)?;
+        let content = encoder.finish().map_err(error::rq_error)?;
+
+        let req = pb::MultiReqBody {
+            subcmd: Some(1),
+            term_type: Some(5),
+            platform_type: Some(9),
+            net_type: Some(3),
+            build_ver: Some("8.2.0.1296".to_owned()),
+            multimsg_applyup_req: vec![pb::MultiMsgApplyUpReq {
+                dst_uin: Some(user_id),
+                msg_size: Some(content.len() as i64),
+                msg_md5: Some(md5::compute(&content).to_vec()),
+                msg_type: Some(3),
+            }],
+            bu_type: Some(2),
+        };
+        let rsp = self
+            .uni_request::<pb::MultiRspBody>("MultiMsg.ApplyUp", req)
+            .await?
+            .multimsg_applyup_rsp
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        if rsp.result() != 0 {
+            return Err(error::rq_error(format!(
+                "multi msg apply up {}",
+                rsp.result()
+            )));
+        }
+        let ticket = rsp.msg_ukey.clone().unwrap_or_default();
+        let body = pb::LongReqBody {
+            subcmd: Some(1),
+            term_type: Some(5),
+            platform_type: Some(9),
+            msg_up_req: vec![pb::LongMsgUpReq {
+                msg_type: Some(3),
+                dst_uin: Some(user_id),
+                msg_content: Some(content),
+                store_type: Some(2),
+                msg_ukey: Some(ticket.clone()),
+            }],
+        };
+        let cli = self.get_client()?;
+        let addr = cli
+            .highway_addrs
+            .read()
+            .await
+            .first()
+            .cloned()
+            .ok_or_else(|| error::rq_error("no highway address"))?;
+        cli.highway_upload_bdh(
+            addr,
+            BdhInput {
+                command_id: UPLOAD_COMMAND,
+                ticket,
+                ext: vec![],
+                encrypt: false,
+                chunk_size: 8192 * 8,
+                send_echo: true,
+            },
+            &body.encode_to_vec(),
+        )
+        .await
+        .map_err(error::rq_error)?;
+        Ok(forward_chain(
+            rsp.msg_resid(),
+            &walle_core::util::new_uuid(),
+            count,
+            &preview,
+        ))
+    }
+}
+
+/// pack nodes as friend messages, nested forwards are packed into `items` and referenced by
+/// their item name
+fn pack_nodes(
+    user_id: i64,
+    forwards: Vec<ForwardMessage>,
+    items: &mut Vec<pb::PbMultiMsgItem>,
+) -> Vec<msg_pb::Message> {
+    let mut msg = vec![];
+    for (seq, forward) in forwards.into_iter().enumerate() {
+        let (sender_id, sender_name, time, elements) = match forward {
+            ForwardMessage::Message(node) => {
+                (node.sender_id, node.sender_name, node.time, node.elements)
+            }
+            ForwardMessage::Forward(node) => {
+                let file_name = walle_core::util::new_uuid();
+                let chain = forward_chain(
+                    "",
+                    &file_name,
+                    node.nodes.len(),
+                    &forward_preview(&node.nodes),
+                );
+                let nested = pack_nodes(user_id, node.nodes, items);
+                items.push(pb::PbMultiMsgItem {
+                    file_name: Some(file_name),
+                    buffer: Some(pb::PbMultiMsgNew { msg: nested }),
+                });
+                (node.sender_id, node.sender_name, node.time, chain)
+            }
+        };
+        msg.push(msg_pb::Message {
+            head: Some(msg_pb::MessageHead {
+                from_uin: Some(sender_id),
+                to_uin: Some(user_id),
+                msg_type: Some(9),
+                c2c_cmd: Some(11),
+                msg_seq: Some(seq as i32),
+                msg_time: Some(time),
+                msg_uid: Some(0x0100_0000_0000_0000 | rand::random::<u32>() as i64),
+                from_nick: Some(sender_name),
+                mutiltrans_head: Some(msg_pb::MutilTransHead {
+                    status: Some(0),
+                    msg_id: Some(1),
+                }),
+                ..Default::default()
+            }),
+            body: Some(msg_pb::MessageBody {
+                rich_text: Some(msg_pb::RichText {
+                    elems: elements.into(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+    msg
+}
+
+mod pb {
+    use ricq_core::pb::msg::Message;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PbMultiMsgTransmit {
+        #[prost(message, repeated, tag = "1")]
+        pub msg: Vec<Message>,
+        #[prost(message, repeated, tag = "2")]
+        pub pb_item_list: Vec<PbMultiMsgItem>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PbMultiMsgItem {
+        #[prost(string, optional, tag = "1")]
+        pub file_name: Option<String>,
+        #[prost(message, optional, tag = "2")]
+        pub buffer: Option<PbMultiMsgNew>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct PbMultiMsgNew {
+        #[prost(message, repeated, tag = "1")]
+        pub msg: Vec<Message>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MultiReqBody {
+        #[prost(int32, optional, tag = "1")]
+        pub subcmd: Option<i32>,
+        #[prost(int32, optional, tag = "2")]
+        pub term_type: Option<i32>,
+        #[prost(int32, optional, tag = "3")]
+        pub platform_type: Option<i32>,
+        #[prost(int32, optional, tag = "4")]
+        pub net_type: Option<i32>,
+        #[prost(string, optional, tag = "5")]
+        pub build_ver: Option<String>,
+        #[prost(message, repeated, tag = "6")]
+        pub multimsg_applyup_req: Vec<MultiMsgApplyUpReq>,
+        #[prost(int32, optional, tag = "8")]
+        pub bu_type: Option<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MultiMsgApplyUpReq {
+        #[prost(int64, optional, tag = "1")]
+        pub dst_uin: Option<i64>,
+        #[prost(int64, optional, tag = "2")]
+        pub msg_size: Option<i64>,
+        #[prost(bytes = "vec", optional, tag = "3")]
+        pub msg_md5: Option<Vec<u8>>,
+        #[prost(int32, optional, tag = "4")]
+        pub msg_type: Option<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MultiRspBody {
+        #[prost(int32, optional, tag = "1")]
+        pub subcmd: Option<i32>,
+        #[prost(message, repeated, tag = "2")]
+        pub multimsg_applyup_rsp: Vec<MultiMsgApplyUpRsp>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MultiMsgApplyUpRsp {
+        #[prost(int32, optional, tag = "1")]
+        pub result: Option<i32>,
+        #[prost(string, optional, tag = "2")]
+        pub msg_resid: Option<String>,
+        #[prost(bytes = "vec", optional, tag = "3")]
+        pub msg_ukey: Option<Vec<u8>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct LongReqBody {
+        #[prost(int32, optional, tag = "1")]
+        pub subcmd: Option<i32>,
+        #[prost(int32, optional, tag = "2")]
+        pub term_type: Option<i32>,
+        #[prost(int32, optional, tag = "3")]
+        pub platform_type: Option<i32>,
+        #[prost(message, repeated, tag = "4")]
+        pub msg_up_req: Vec<LongMsgUpReq>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct LongMsgUpReq {
+        #[prost(int32, optional, tag = "1")]
+        pub msg_type: Option<i32>,
+        #[prost(int64, optional, tag = "2")]
+        pub dst_uin: Option<i64>,
+        #[prost(bytes = "vec", optional, tag = "4")]
+        pub msg_content: Option<Vec<u8>>,
+        #[prost(int32, optional, tag = "5")]
+        pub store_type: Option<i32>,
+        #[prost(bytes = "vec", optional, tag = "6")]
+        pub msg_ukey: Option<Vec<u8>>,
+    }
+}
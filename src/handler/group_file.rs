@@ -0,0 +1,780 @@
+use std::path::Path;
+
+use prost::Message;
+use ricq_core::highway::BdhInput;
+use sha1::{Digest, Sha1};
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use walle_core::resp::{Resp, RespError};
+use walle_core::util::Value;
+use walle_core::value;
+
+use crate::error;
+use crate::model::{
+    CreateGroupFolder, GetGroupFiles, GroupFile, GroupFolder, QQAction, RenameGroupFolder,
+    UploadGroupFile,
+};
+
+use super::RespResult;
+
+/// app id of group files in oidb requests
+const APP_ID: u32 = 3;
+/// bus id of permanent group files
+const BUS_ID: u32 = 102;
+const ROOT_FOLDER: &str = "/";
+/// highway command of group file uploads
+const UPLOAD_COMMAND: i32 = 71;
+/// ricq uploads to highway from a slice, so larger files are refused instead of read into
+/// memory
+const MAX_UPLOAD_SIZE: u64 = 100 * 1024 * 1024;
+
+impl super::Handler {
+    pub(super) async fn handle_qq(&self, action: QQAction) -> RespResult<Resp> {
+        match action {
+            QQAction::GetGroupFiles(c) => self.get_group_files(c).await,
+            QQAction::GetGroupFileUrl(c) => self.get_group_file_url(c).await,
+            QQAction::UploadGroupFile(c) => self.upload_group_file(c).await,
+            QQAction::CreateGroupFolder(c) => self.create_group_folder(c).await,
+            QQAction::RenameGroupFolder(c) => self.rename_group_folder(c).await,
+            QQAction::DeleteGroupFolder(c) => self.delete_group_folder(c).await,
+            QQAction::DeleteGroupFile(c) => self.delete_group_file(c).await,
+        }
+    }
+
+    async fn get_group_files(&self, c: GetGroupFiles) -> RespResult<Resp> {
+        let group_code = parse_group_id(&c.group_id)?;
+        let folder_id = c.folder_id.unwrap_or_else(|| ROOT_FOLDER.to_owned());
+        let (mut folders, mut files) = (vec![], vec![]);
+        let mut start_index = 0;
+        loop {
+            let req = pb::D6d8ReqBody {
+                file_list_info_req: Some(pb::GetFileListReqBody {
+                    group_code: Some(group_code),
+                    app_id: Some(APP_ID),
+                    folder_id: Some(folder_id.clone()),
+                    file_count: Some(100),
+                    all_file_count: Some(0),
+                    req_from: Some(3),
+                    // by upload time
+                    sort_by: Some(1),
+                    filter_code: Some(0),
+                    uin: Some(0),
+                    start_index: Some(start_index),
+                    context: Some(vec![]),
+                }),
+            };
+            let rsp = self
+                .oidb::<pb::D6d8RspBody>(0x6d8, 1, req)
+                .await?
+                .file_list_info_rsp
+                .unwrap_or_default();
+            check_ret(rsp.ret_code, &rsp.ret_msg)?;
+            for item in rsp.item_list {
+                if let Some(folder) = item.folder_info {
+                    folders.push(folder_value(folder));
+                }
+                if let Some(file) = item.file_info {
+                    files.push(file_value(group_code, file));
+                }
+            }
+            if rsp.is_end || rsp.next_index <= start_index {
+                break;
+            }
+            start_index = rsp.next_index;
+        }
+        Ok(value!({ "folders": folders, "files": files }).into())
+    }
+
+    async fn get_group_file_url(&self, c: GroupFile) -> RespResult<Resp> {
+        let req = pb::D6d6ReqBody {
+            download_file_req: Some(pb::DownloadFileReqBody {
+                group_code: Some(parse_group_id(&c.group_id)?),
+                app_id: Some(APP_ID),
+                bus_id: Some(c.busid as u32),
+                file_id: Some(c.file_id.clone()),
+            }),
+            ..Default::default()
+        };
+        let rsp = self
+            .oidb::<pb::D6d6RspBody>(0x6d6, 2, req)
+            .await?
+            .download_file_rsp
+            .unwrap_or_default();
+        check_ret(rsp.ret_code, &rsp.ret_msg)?;
+        let url = format!(
+            "http://{}/ftn_handler/{}/?fname={}",
+            rsp.download_ip,
+            hex::encode(&rsp.download_url),
+            hex::encode(&c.file_id)
+        );
+        Ok(value!({ "url": url }).into())
+    }
+
+    async fn upload_group_file(&self, c: UploadGroupFile) -> RespResult<Resp> {
+        let group_code = parse_group_id(&c.group_id)?;
+        let stored = self.stored_file(&c.file_id).await?;
+        if stored.size > MAX_UPLOAD_SIZE {
+            return Err(error::group_file_error(format!(
+                "file size {} over the upload limit {}",
+                stored.size, MAX_UPLOAD_SIZE
+            )));
+        }
+        let name = c.name.unwrap_or_else(|| stored.name.clone());
+        let path = stored.path(&self.data_path);
+        let (md5, sha1) = file_digests(&path).await?;
+        let req = pb::D6d6ReqBody {
+            upload_file_req: Some(pb::UploadFileReqBody {
+                group_code: Some(group_code),
+                app_id: Some(APP_ID),
+                bus_id: Some(BUS_ID),
+                entrance: Some(5),
+                parent_folder_id: Some(c.folder_id.unwrap_or_else(|| ROOT_FOLDER.to_owned())),
+                file_name: Some(name.clone()),
+                local_path: Some(format!("/storage/emulated/0/Download/{}", name)),
+                int64_file_size: Some(stored.size),
+                sha: Some(sha1.clone()),
+                md5: Some(md5.clone()),
+                support_multi_upload: Some(true),
+            }),
+            ..Default::default()
+        };
+        let rsp = self
+            .oidb::<pb::D6d6RspBody>(0x6d6, 0, req)
+            .await?
+            .upload_file_rsp
+            .unwrap_or_default();
+        check_ret(rsp.ret_code, &rsp.ret_msg)?;
+        let cli = self.get_client()?;
+        if !rsp.bool_file_exist {
+            let uin = cli.uin().await;
+            let ext = pb::GroupFileUploadExt {
+                unknown1: Some(100),
+                unknown2: Some(1),
+                unknown3: Some(0),
+                entry: Some(pb::GroupFileUploadEntry {
+                    busi_buff: Some(pb::ExcitingBusiInfo {
+                        bus_id: Some(rsp.bus_id),
+                        sender_uin: Some(uin),
+                        receiver_uin: Some(group_code as i64),
+                        group_code: Some(group_code as i64),
+                    }),
+                    file_entry: Some(pb::ExcitingFileEntry {
+                        file_size: Some(stored.size as i64),
+                        md5: Some(md5),
+                        sha1: Some(sha1),
+                        file_id: Some(rsp.file_id.clone().into_bytes()),
+                        upload_key: Some(rsp.check_key.clone()),
+                    }),
+                    client_info: Some(pb::ExcitingClientInfo {
+                        client_type: Some(2),
+                        app_id: Some(cli.engine.read().await.transport.version.app_id.to_string()),
+                        terminal_type: Some(2),
+                        client_ver: Some("9e9c09dc".to_owned()),
+                        unknown: Some(4),
+                    }),
+                    file_name_info: Some(pb::ExcitingFileNameInfo {
+                        file_name: Some(name),
+                    }),
+                    host: Some(pb::ExcitingHostConfig {
+                        hosts: vec![pb::ExcitingHostInfo {
+                            url: Some(pb::ExcitingUrlInfo {
+                                unknown: Some(1),
+                                host: Some(rsp.upload_ip.clone()),
+                            }),
+                            port: Some(rsp.upload_port as i32),
+                        }],
+                    }),
+                }),
+            };
+            let addr = cli
+                .highway_addrs
+                .read()
+                .await
+                .first()
+                .cloned()
+                .ok_or_else(|| error::group_file_error("no highway address"))?;
+            let ticket = cli.highway_session.read().await.sig_session.to_vec();
+            let data = tokio::fs::read(&path)
+                .await
+                .map_err(error::file_read_error)?;
+            cli.highway_upload_bdh(
+                addr,
+                BdhInput {
+                    command_id: UPLOAD_COMMAND,
+                    ticket,
+                    ext: ext.encode_to_vec(),
+                    encrypt: false,
+                    chunk_size: 256 * 1024,
+                    send_echo: true,
+                },
+                &data,
+            )
+            .await
+            .map_err(error::rq_error)?;
+        }
+        // the file shows in the group chat only after its feed is sent
+        let req = pb::D6d9ReqBody {
+            feeds_info_req: Some(pb::FeedsReqBody {
+                group_code: Some(group_code),
+                app_id: Some(APP_ID),
+                feeds_info_list: vec![pb::GroupFileFeedsInfo {
+                    bus_id: Some(rsp.bus_id as u32),
+                    file_id: Some(rsp.file_id.clone()),
+                    msg_random: Some(rand::random()),
+                    feed_flag: Some(1),
+                }],
+            }),
+        };
+        let feeds = self
+            .oidb::<pb::D6d9RspBody>(0x6d9, 4, req)
+            .await?
+            .feeds_info_rsp
+            .unwrap_or_default();
+        check_ret(feeds.ret_code, &feeds.ret_msg)?;
+        Ok(value!({ "file_id": rsp.file_id, "busid": rsp.bus_id as i64 }).into())
+    }
+
+    async fn create_group_folder(&self, c: CreateGroupFolder) -> RespResult<Resp> {
+        let req = pb::D6d7ReqBody {
+            create_folder_req: Some(pb::CreateFolderReqBody {
+                group_code: Some(parse_group_id(&c.group_id)?),
+                app_id: Some(APP_ID),
+                // folders can only be created in the root folder
+                parent_folder_id: Some(ROOT_FOLDER.to_owned()),
+                folder_name: Some(c.name),
+            }),
+            ..Default::default()
+        };
+        let rsp = self
+            .oidb::<pb::D6d7RspBody>(0x6d7, 0, req)
+            .await?
+            .create_folder_rsp
+            .unwrap_or_default();
+        check_ret(rsp.ret_code, &rsp.ret_msg)?;
+        let folder = rsp.folder_info.map(folder_value).unwrap_or(Value::Null);
+        Ok(folder.into())
+    }
+
+    async fn rename_group_folder(&self, c: RenameGroupFolder) -> RespResult<Resp> {
+        let req = pb::D6d7ReqBody {
+            rename_folder_req: Some(pb::RenameFolderReqBody {
+                group_code: Some(parse_group_id(&c.group_id)?),
+                app_id: Some(APP_ID),
+                folder_id: Some(c.folder_id),
+                new_folder_name: Some(c.name),
+            }),
+            ..Default::default()
+        };
+        let rsp = self
+            .oidb::<pb::D6d7RspBody>(0x6d7, 2, req)
+            .await?
+            .rename_folder_rsp
+            .unwrap_or_default();
+        check_ret(rsp.ret_code, &rsp.ret_msg)?;
+        Ok(Value::Null.into())
+    }
+
+    async fn delete_group_folder(&self, c: GroupFolder) -> RespResult<Resp> {
+        let req = pb::D6d7ReqBody {
+            delete_folder_req: Some(pb::DeleteFolderReqBody {
+                group_code: Some(parse_group_id(&c.group_id)?),
+                app_id: Some(APP_ID),
+                folder_id: Some(c.folder_id),
+            }),
+            ..Default::default()
+        };
+        let rsp = self
+            .oidb::<pb::D6d7RspBody>(0x6d7, 1, req)
+            .await?
+            .delete_folder_rsp
+            .unwrap_or_default();
+        check_ret(rsp.ret_code, &rsp.ret_msg)?;
+        Ok(Value::Null.into())
+    }
+
+    async fn delete_group_file(&self, c: GroupFile) -> RespResult<Resp> {
+        let req = pb::D6d6ReqBody {
+            delete_file_req: Some(pb::DeleteFileReqBody {
+                group_code: Some(parse_group_id(&c.group_id)?),
+                app_id: Some(APP_ID),
+                bus_id: Some(c.busid as u32),
+                parent_folder_id: Some(c.folder_id.unwrap_or_else(|| ROOT_FOLDER.to_owned())),
+                file_id: Some(c.file_id),
+            }),
+            ..Default::default()
+        };
+        let rsp = self
+            .oidb::<pb::D6d6RspBody>(0x6d6, 3, req)
+            .await?
+            .delete_file_rsp
+            .unwrap_or_default();
+        check_ret(rsp.ret_code, &rsp.ret_msg)?;
+        Ok(Value::Null.into())
+    }
+}
+
+fn parse_group_id(group_id: &str) -> RespResult<u64> {
+    group_id.parse().map_err(|_| error::bad_param("group_id"))
+}
+
+/// md5 and sha1 of the file at `path` read in chunks
+async fn file_digests(path: &Path) -> RespResult<(Vec<u8>, Vec<u8>)> {
+    let mut file = File::open(path).await.map_err(error::file_read_error)?;
+    let (mut md5, mut sha1) = (md5::Context::new(), Sha1::new());
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await.map_err(error::file_read_error)?;
+        if n == 0 {
+            break;
+        }
+        md5.consume(&buf[..n]);
+        sha1.update(&buf[..n]);
+    }
+    Ok((md5.compute().to_vec(), sha1.finalize().to_vec()))
+}
+
+/// a non-zero `ret_code` is a refusal of the server, e.g. no permission or name conflict
+fn check_ret(ret_code: i32, ret_msg: &str) -> Result<(), RespError> {
+    if ret_code != 0 {
+        return Err(error::group_file_error(format!("{} {}", ret_code, ret_msg)));
+    }
+    Ok(())
+}
+
+fn folder_value(folder: pb::GroupFolderInfo) -> Value {
+    value!({
+        "folder_id": folder.folder_id,
+        "parent_id": folder.parent_folder_id,
+        "name": folder.folder_name,
+        "create_time": folder.create_time as i64,
+        "creator_id": folder.create_uin.to_string(),
+        "total_file_count": folder.total_file_count as i64
+    })
+}
+
+fn file_value(group_code: u64, file: pb::GroupFileInfo) -> Value {
+    value!({
+        "group_id": group_code.to_string(),
+        "file_id": file.file_id,
+        "busid": file.bus_id as i64,
+        "folder_id": file.parent_folder_id,
+        "name": file.file_name,
+        "size": file.file_size as i64,
+        "upload_time": file.upload_time as i64,
+        "dead_time": file.dead_time as i64,
+        "download_times": file.download_times as i64,
+        "uploader_id": file.uploader_uin.to_string(),
+        "uploader_name": file.uploader_name
+    })
+}
+
+/// oidb 0x6d6 to 0x6d9 and the highway ext of group file uploads, field numbers follow the
+/// protocol, unused fields are left out
+mod pb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GroupFileInfo {
+        #[prost(string, tag = "1")]
+        pub file_id: String,
+        #[prost(string, tag = "2")]
+        pub file_name: String,
+        #[prost(uint64, tag = "3")]
+        pub file_size: u64,
+        #[prost(uint32, tag = "4")]
+        pub bus_id: u32,
+        #[prost(uint32, tag = "6")]
+        pub upload_time: u32,
+        #[prost(uint32, tag = "7")]
+        pub dead_time: u32,
+        #[prost(uint32, tag = "9")]
+        pub download_times: u32,
+        #[prost(string, tag = "14")]
+        pub uploader_name: String,
+        #[prost(uint64, tag = "15")]
+        pub uploader_uin: u64,
+        #[prost(string, tag = "16")]
+        pub parent_folder_id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GroupFolderInfo {
+        #[prost(string, tag = "1")]
+        pub folder_id: String,
+        #[prost(string, tag = "2")]
+        pub parent_folder_id: String,
+        #[prost(string, tag = "3")]
+        pub folder_name: String,
+        #[prost(uint32, tag = "4")]
+        pub create_time: u32,
+        #[prost(uint64, tag = "6")]
+        pub create_uin: u64,
+        #[prost(uint32, tag = "8")]
+        pub total_file_count: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct D6d8ReqBody {
+        #[prost(message, optional, tag = "2")]
+        pub file_list_info_req: Option<GetFileListReqBody>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetFileListReqBody {
+        #[prost(uint64, optional, tag = "1")]
+        pub group_code: Option<u64>,
+        #[prost(uint32, optional, tag = "2")]
+        pub app_id: Option<u32>,
+        #[prost(string, optional, tag = "3")]
+        pub folder_id: Option<String>,
+        #[prost(uint32, optional, tag = "4")]
+        pub file_count: Option<u32>,
+        #[prost(uint32, optional, tag = "5")]
+        pub all_file_count: Option<u32>,
+        #[prost(uint32, optional, tag = "6")]
+        pub req_from: Option<u32>,
+        #[prost(uint32, optional, tag = "7")]
+        pub sort_by: Option<u32>,
+        #[prost(uint32, optional, tag = "8")]
+        pub filter_code: Option<u32>,
+        #[prost(uint64, optional, tag = "9")]
+        pub uin: Option<u64>,
+        #[prost(uint32, optional, tag = "11")]
+        pub start_index: Option<u32>,
+        #[prost(bytes = "vec", optional, tag = "12")]
+        pub context: Option<Vec<u8>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct D6d8RspBody {
+        #[prost(message, optional, tag = "2")]
+        pub file_list_info_rsp: Option<GetFileListRspBody>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GetFileListRspBody {
+        #[prost(int32, tag = "1")]
+        pub ret_code: i32,
+        #[prost(string, tag = "2")]
+        pub ret_msg: String,
+        #[prost(bool, tag = "4")]
+        pub is_end: bool,
+        #[prost(message, repeated, tag = "5")]
+        pub item_list: Vec<FileListItem>,
+        #[prost(uint32, tag = "13")]
+        pub next_index: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FileListItem {
+        #[prost(uint32, tag = "1")]
+        pub r#type: u32,
+        #[prost(message, optional, tag = "2")]
+        pub folder_info: Option<GroupFolderInfo>,
+        #[prost(message, optional, tag = "3")]
+        pub file_info: Option<GroupFileInfo>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct D6d6ReqBody {
+        #[prost(message, optional, tag = "1")]
+        pub upload_file_req: Option<UploadFileReqBody>,
+        #[prost(message, optional, tag = "3")]
+        pub download_file_req: Option<DownloadFileReqBody>,
+        #[prost(message, optional, tag = "4")]
+        pub delete_file_req: Option<DeleteFileReqBody>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct UploadFileReqBody {
+        #[prost(uint64, optional, tag = "1")]
+        pub group_code: Option<u64>,
+        #[prost(uint32, optional, tag = "2")]
+        pub app_id: Option<u32>,
+        #[prost(uint32, optional, tag = "3")]
+        pub bus_id: Option<u32>,
+        #[prost(uint32, optional, tag = "4")]
+        pub entrance: Option<u32>,
+        #[prost(string, optional, tag = "5")]
+        pub parent_folder_id: Option<String>,
+        #[prost(string, optional, tag = "6")]
+        pub file_name: Option<String>,
+        #[prost(string, optional, tag = "7")]
+        pub local_path: Option<String>,
+        #[prost(uint64, optional, tag = "8")]
+        pub int64_file_size: Option<u64>,
+        #[prost(bytes = "vec", optional, tag = "9")]
+        pub sha: Option<Vec<u8>>,
+        #[prost(bytes = "vec", optional, tag = "11")]
+        pub md5: Option<Vec<u8>>,
+        #[prost(bool, optional, tag = "15")]
+        pub support_multi_upload: Option<bool>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DownloadFileReqBody {
+        #[prost(uint64, optional, tag = "1")]
+        pub group_code: Option<u64>,
+        #[prost(uint32, optional, tag = "2")]
+        pub app_id: Option<u32>,
+        #[prost(uint32, optional, tag = "3")]
+        pub bus_id: Option<u32>,
+        #[prost(string, optional, tag = "4")]
+        pub file_id: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DeleteFileReqBody {
+        #[prost(uint64, optional, tag = "1")]
+        pub group_code: Option<u64>,
+        #[prost(uint32, optional, tag = "2")]
+        pub app_id: Option<u32>,
+        #[prost(uint32, optional, tag = "3")]
+        pub bus_id: Option<u32>,
+        #[prost(string, optional, tag = "4")]
+        pub parent_folder_id: Option<String>,
+        #[prost(string, optional, tag = "5")]
+        pub file_id: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct D6d6RspBody {
+        #[prost(message, optional, tag = "1")]
+        pub upload_file_rsp: Option<UploadFileRspBody>,
+        #[prost(message, optional, tag = "3")]
+        pub download_file_rsp: Option<DownloadFileRspBody>,
+        #[prost(message, optional, tag = "4")]
+        pub delete_file_rsp: Option<RetRspBody>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct UploadFileRspBody {
+        #[prost(int32, tag = "1")]
+        pub ret_code: i32,
+        #[prost(string, tag = "2")]
+        pub ret_msg: String,
+        #[prost(string, tag = "4")]
+        pub upload_ip: String,
+        #[prost(int32, tag = "6")]
+        pub bus_id: i32,
+        #[prost(string, tag = "7")]
+        pub file_id: String,
+        #[prost(bytes = "vec", tag = "8")]
+        pub check_key: Vec<u8>,
+        #[prost(bool, tag = "10")]
+        pub bool_file_exist: bool,
+        #[prost(uint32, tag = "14")]
+        pub upload_port: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DownloadFileRspBody {
+        #[prost(int32, tag = "1")]
+        pub ret_code: i32,
+        #[prost(string, tag = "2")]
+        pub ret_msg: String,
+        #[prost(string, tag = "4")]
+        pub download_ip: String,
+        #[prost(bytes = "vec", tag = "6")]
+        pub download_url: Vec<u8>,
+    }
+
+    /// response of the requests answering only a result
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RetRspBody {
+        #[prost(int32, tag = "1")]
+        pub ret_code: i32,
+        #[prost(string, tag = "2")]
+        pub ret_msg: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct D6d7ReqBody {
+        #[prost(message, optional, tag = "1")]
+        pub create_folder_req: Option<CreateFolderReqBody>,
+        #[prost(message, optional, tag = "2")]
+        pub delete_folder_req: Option<DeleteFolderReqBody>,
+        #[prost(message, optional, tag = "3")]
+        pub rename_folder_req: Option<RenameFolderReqBody>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CreateFolderReqBody {
+        #[prost(uint64, optional, tag = "1")]
+        pub group_code: Option<u64>,
+        #[prost(uint32, optional, tag = "2")]
+        pub app_id: Option<u32>,
+        #[prost(string, optional, tag = "3")]
+        pub parent_folder_id: Option<String>,
+        #[prost(string, optional, tag = "4")]
+        pub folder_name: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct DeleteFolderReqBody {
+        #[prost(uint64, optional, tag = "1")]
+        pub group_code: Option<u64>,
+        #[prost(uint32, optional, tag = "2")]
+        pub app_id: Option<u32>,
+        #[prost(string, optional, tag = "3")]
+        pub folder_id: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RenameFolderReqBody {
+        #[prost(uint64, optional, tag = "1")]
+        pub group_code: Option<u64>,
+        #[prost(uint32, optional, tag = "2")]
+        pub app_id: Option<u32>,
+        #[prost(string, optional, tag = "3")]
+        pub folder_id: Option<String>,
+        #[prost(string, optional, tag = "4")]
+        pub new_folder_name: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct D6d7RspBody {
+        #[prost(message, optional, tag = "1")]
+        pub create_folder_rsp: Option<CreateFolderRspBody>,
+        #[prost(message, optional, tag = "2")]
+        pub delete_folder_rsp: Option<RetRspBody>,
+        #[prost(message, optional, tag = "3")]
+        pub rename_folder_rsp: Option<RetRspBody>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CreateFolderRspBody {
+        #[prost(int32, tag = "1")]
+        pub ret_code: i32,
+        #[prost(string, tag = "2")]
+        pub ret_msg: String,
+        #[prost(message, optional, tag = "4")]
+        pub folder_info: Option<GroupFolderInfo>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct D6d9ReqBody {
+        #[prost(message, optional, tag = "5")]
+        pub feeds_info_req: Option<FeedsReqBody>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FeedsReqBody {
+        #[prost(uint64, optional, tag = "1")]
+        pub group_code: Option<u64>,
+        #[prost(uint32, optional, tag = "2")]
+        pub app_id: Option<u32>,
+        #[prost(message, repeated, tag = "3")]
+        pub feeds_info_list: Vec<GroupFileFeedsInfo>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GroupFileFeedsInfo {
+        #[prost(uint32, optional, tag = "1")]
+        pub bus_id: Option<u32>,
+        #[prost(string, optional, tag = "2")]
+        pub file_id: Option<String>,
+        #[prost(uint32, optional, tag = "3")]
+        pub msg_random: Option<u32>,
+        #[prost(uint32, optional, tag = "5")]
+        pub feed_flag: Option<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct D6d9RspBody {
+        #[prost(message, optional, tag = "5")]
+        pub feeds_info_rsp: Option<RetRspBody>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GroupFileUploadExt {
+        #[prost(int32, optional, tag = "1")]
+        pub unknown1: Option<i32>,
+        #[prost(int32, optional, tag = "2")]
+        pub unknown2: Option<i32>,
+        #[prost(int32, optional, tag = "3")]
+        pub unknown3: Option<i32>,
+        #[prost(message, optional, tag = "100")]
+        pub entry: Option<GroupFileUploadEntry>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct GroupFileUploadEntry {
+        #[prost(message, optional, tag = "100")]
+        pub busi_buff: Option<ExcitingBusiInfo>,
+        #[prost(message, optional, tag = "200")]
+        pub file_entry: Option<ExcitingFileEntry>,
+        #[prost(message, optional, tag = "300")]
+        pub client_info: Option<ExcitingClientInfo>,
+        #[prost(message, optional, tag = "400")]
+        pub file_name_info: Option<ExcitingFileNameInfo>,
+        #[prost(message, optional, tag = "500")]
+        pub host: Option<ExcitingHostConfig>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExcitingBusiInfo {
+        #[prost(int32, optional, tag = "1")]
+        pub bus_id: Option<i32>,
+        #[prost(int64, optional, tag = "100")]
+        pub sender_uin: Option<i64>,
+        #[prost(int64, optional, tag = "200")]
+        pub receiver_uin: Option<i64>,
+        #[prost(int64, optional, tag = "400")]
+        pub group_code: Option<i64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExcitingFileEntry {
+        #[prost(int64, optional, tag = "100")]
+        pub file_size: Option<i64>,
+        #[prost(bytes = "vec", optional, tag = "200")]
+        pub md5: Option<Vec<u8>>,
+        #[prost(bytes = "vec", optional, tag = "300")]
+        pub sha1: Option<Vec<u8>>,
+        #[prost(bytes = "vec", optional, tag = "600")]
+        pub file_id: Option<Vec<u8>>,
+        #[prost(bytes = "vec", optional, tag = "700")]
+        pub upload_key: Option<Vec<u8>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExcitingClientInfo {
+        #[prost(int32, optional, tag = "100")]
+        pub client_type: Option<i32>,
+        #[prost(string, optional, tag = "200")]
+        pub app_id: Option<String>,
+        #[prost(int32, optional, tag = "300")]
+        pub terminal_type: Option<i32>,
+        #[prost(string, optional, tag = "400")]
+        pub client_ver: Option<String>,
+        #[prost(int32, optional, tag = "600")]
+        pub unknown: Option<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExcitingFileNameInfo {
+        #[prost(string, optional, tag = "100")]
+        pub file_name: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExcitingHostConfig {
+        #[prost(message, repeated, tag = "200")]
+        pub hosts: Vec<ExcitingHostInfo>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExcitingHostInfo {
+        #[prost(message, optional, tag = "1")]
+        pub url: Option<ExcitingUrlInfo>,
+        #[prost(int32, optional, tag = "2")]
+        pub port: Option<i32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ExcitingUrlInfo {
+        #[prost(int32, optional, tag = "1")]
+        pub unknown: Option<i32>,
+        #[prost(string, optional, tag = "2")]
+        pub host: Option<String>,
+    }
+}
@@ -1,34 +1,42 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use cached::{SizedCache, TimedCache};
+use cached::SizedCache;
 use once_cell::sync::OnceCell;
 use ricq::client::Client;
 use ricq::structs::{FriendAudio, GroupAudio};
 use tokio::sync::Mutex;
 use walle_core::structs::Selft;
-use walle_core::util::ValueMapExt;
 use walle_core::GetVersion;
 use walle_core::{
     action::*,
     event::*,
     resp::*,
+    segment::MsgSegment,
     structs::{GroupInfo, SendMessageResp, UserInfo},
     GetStatus,
 };
 
-use crate::database::{Database, MessageId, WQDatabase};
+use crate::config::FetchConfig;
+use crate::database::{
+    HistoryQuery, MessageId, RecallState, SearchQuery, StoredMessageId, WQDatabase,
+};
 use crate::error::{self, map_action_parse_error};
 use crate::model::*;
 use crate::parse::util::{
     decode_message_id, new_group_receipt, new_group_temp_receipt, new_private_receipt,
 };
-use crate::parse::{MsgChainBuilder, RQSendItem};
+use crate::parse::{upload_forward, MsgChainBuilder, RQSendItem};
 
+pub(crate) use self::file::spawn_fragment_cleaner;
 pub use self::file::FragmentFile;
 
 mod file;
+mod forward;
+mod group_file;
 mod impls;
 mod infos;
+mod packet;
 
 pub(crate) use infos::Infos;
 
@@ -37,14 +45,21 @@ pub struct Handler {
     pub(crate) data_path: Arc<String>,
     pub(crate) event_cache: Arc<Mutex<SizedCache<String, Event>>>,
     pub(crate) database: Arc<WQDatabase>,
-    pub(crate) uploading_fragment: Arc<Mutex<TimedCache<String, FragmentFile>>>,
+    pub(crate) uploading_fragment: Arc<Mutex<HashMap<String, FragmentFile>>>,
     pub(crate) infos: Arc<Infos>,
+    pub(crate) fetch: Arc<FetchConfig>,
 }
 
 use crate::model::WQAction;
 
 impl Handler {
-    async fn _handle(&self, action: Action) -> Result<Resp, RespError> {
+    async fn _handle(&self, mut action: Action) -> Result<Resp, RespError> {
+        if let Some(name) = action.action.strip_prefix(QQ_ACTION_PREFIX) {
+            action.action = name.to_owned();
+            return self
+                .handle_qq(QQAction::try_from(action).map_err(map_action_parse_error)?)
+                .await;
+        }
         match WQAction::try_from(action).map_err(map_action_parse_error)? {
             WQAction::GetLatestEvents(c) => self.get_latest_events(c).await.map(Into::into),
             WQAction::GetSupportedActions {} => Self::get_supported_actions().map(Into::into),
@@ -54,6 +69,9 @@ impl Handler {
             WQAction::SendMessage(c) => self.send_message(c).await.map(Into::into),
             WQAction::DeleteMessage(c) => self.delete_message(c).await.map(Into::into),
             WQAction::GetMessage(c) => self.get_message(c).await.map(Into::into),
+            WQAction::GetMessageHistory(c) => self.get_message_history(c).await.map(Into::into),
+            WQAction::SearchMessages(c) => self.search_messages(c).await.map(Into::into),
+            WQAction::GetForwardMessage(c) => self.get_forward_message(c).await.map(Into::into),
 
             WQAction::GetSelfInfo {} => self.get_self_info().await.map(Into::into),
             WQAction::GetUserInfo(c) => self.get_user_info(c).await.map(Into::into),
@@ -84,11 +102,10 @@ impl Handler {
                 .map(Into::into),
 
             WQAction::UploadFile(c) => self.upload_file(c).await.map(Into::into),
-            WQAction::UploadFileFragmented(c) => {
-                self.upload_file_fragmented(c).await.map(Into::into)
-            }
+            WQAction::UploadFileFragmented(c) => self.upload_file_fragmented(c).await,
             WQAction::GetFile(c) => self.get_file(c).await.map(Into::into),
             WQAction::GetFileFragmented(c) => self.get_file_fragmented(c).await,
+            WQAction::GetMissingFragments(c) => self.get_missing_fragments(c).await,
             WQAction::SetNewFriend(c) => self.set_new_friend(c).await.map(Into::into),
             WQAction::DeleteFriend(c) => self.delete_friend(c).await.map(Into::into),
             WQAction::GetNewFriendRequests {} => {
@@ -162,6 +179,17 @@ impl Handler {
             "set_new_friend",
             "delete_friend",
             "get_new_friend_request",
+            "get_message_history",
+            "search_messages",
+            "get_missing_fragments",
+            "get_forward_message",
+            "qq.get_group_files",
+            "qq.get_group_file_url",
+            "qq.upload_group_file",
+            "qq.create_group_folder",
+            "qq.rename_group_folder",
+            "qq.delete_group_folder",
+            "qq.delete_group_file",
         ])
     }
 
@@ -219,21 +247,29 @@ impl Handler {
                 let group_code = group_id.parse().map_err(|_| error::bad_param("group_id"))?;
                 let target_id = c.user_id.ok_or_else(|| error::bad_param("user_id"))?;
                 let target = target_id.parse().map_err(|_| error::bad_param("user_id"))?;
-                let receipt =
-                    match MsgChainBuilder::private_chain_builder(self.get_client()?, &self, target)
-                        .build(c.message.clone())
-                        .await?
-                    {
-                        RQSendItem::Chain(chain) => self
-                            .get_client()?
-                            .send_group_temp_message(group_code, target, chain)
+                let receipt = match MsgChainBuilder::group_temp_chain_builder(
+                    self.get_client()?,
+                    &self,
+                    group_code,
+                    target,
+                )
+                .build(c.message.clone())
+                .await?
+                {
+                    RQSendItem::Chain(chain) => self
+                        .get_client()?
+                        .send_group_temp_message(group_code, target, chain)
+                        .await
+                        .map_err(error::rq_error)?,
+                    RQSendItem::Forward(msgs) => {
+                        let cli = self.get_client()?;
+                        let chain = upload_forward(cli, group_code, msgs).await?;
+                        cli.send_group_temp_message(group_code, target, chain)
                             .await
-                            .map_err(error::rq_error)?,
-                        RQSendItem::Forward(_) => {
-                            return Err(resp_error::unsupported_param("forward"))
-                        }
-                        RQSendItem::Voice(_) => return Err(resp_error::unsupported_param("voice")),
-                    };
+                            .map_err(error::rq_error)?
+                    }
+                    RQSendItem::Voice(_) => return Err(resp_error::unsupported_param("voice")),
+                };
                 let cli = self.get_client()?;
                 let time = receipt.time as f64;
                 let event = new_group_temp_receipt(
@@ -270,7 +306,13 @@ impl Handler {
                             .send_friend_audio(target, FriendAudio(ptt))
                             .await
                             .map_err(error::rq_error)?,
-                        _ => return Err(resp_error::unsupported_segment("forward")),
+                        RQSendItem::Forward(msgs) => {
+                            let chain = self.upload_friend_forward(target, msgs).await?;
+                            self.get_client()?
+                                .send_friend_message(target, chain)
+                                .await
+                                .map_err(error::rq_error)?
+                        }
                     };
                 let cli = self.get_client()?;
                 let time = receipt.time as f64;
@@ -297,38 +339,31 @@ impl Handler {
         let message = if c.message_id.contains(' ') {
             decode_message_id(&c.message_id)?
         } else {
+            let id = StoredMessageId::parse(&c.message_id)
+                .ok_or_else(|| error::bad_param("message_id"))?;
             let event = self
                 .database
                 .get_message(&c.message_id)
-                .ok_or(resp_error::database_error("message event not found."))?;
-            if &event.event.detail_type == "private" {
-                (
-                    event
-                        .event
-                        .extra
-                        .get_downcast::<String>("user_id")
-                        .unwrap()
-                        .parse()
-                        .unwrap(), // todo
+                .await?
+                .ok_or_else(|| error::message_not_exist(&c.message_id))?;
+            // the conversation is the recall target, user_id of a sent message is the bot
+            let target = id
+                .conversation
+                .parse()
+                .map_err(|_| error::bad_param("message_id"))?;
+            match id.detail_type.as_str() {
+                "group" => (target, event.seqs, event.rands, None),
+                "private" => (
+                    target,
                     event.seqs,
                     event.rands,
                     Some(event.event.time as i32),
-                )
-            } else {
-                (
-                    event
-                        .event
-                        .extra
-                        .get_downcast::<String>("group_id")
-                        .unwrap()
-                        .parse()
-                        .unwrap(), // todo
-                    event.seqs,
-                    event.rands,
-                    None,
-                )
+                ),
+                // ricq can not recall group_temp messages
+                ty => return Err(resp_error::unsupported_param(ty)),
             }
         };
+        let recalled_at = walle_core::util::timestamp_nano_f64();
         match message.3 {
             Some(time) => self
                 .get_client()?
@@ -341,21 +376,108 @@ impl Handler {
                 .await
                 .map_err(error::rq_error)?,
         }
+        // no recall event is pushed for messages recalled by the bot itself
+        if self.database.not_empty() && !c.message_id.contains(' ') {
+            self.database.mark_recalled(
+                c.message_id,
+                RecallState {
+                    time: recalled_at,
+                    operator_id: self.selft().await?.user_id,
+                },
+            );
+        }
         Ok(())
     }
 
     async fn get_message(&self, c: GetMessage) -> RespResult<Event> {
-        if let Some(m) = self.database.get_message(
-            &c.message_id
-                .parse::<String>()
-                .map_err(|_| error::bad_param("message_id"))?,
-        ) {
+        if let Some(m) = self
+            .database
+            .get_message(
+                &c.message_id
+                    .parse::<String>()
+                    .map_err(|_| error::bad_param("message_id"))?,
+            )
+            .await?
+        {
             Ok(m.event)
         } else {
             Err(error::message_not_exist(c.message_id))
         }
     }
 
+    async fn get_message_history(&self, c: GetMessageHistory) -> RespResult<Vec<Event>> {
+        if !self.database.not_empty() {
+            return Err(resp_error::database_error("database not enabled"));
+        }
+        let bot = self.selft().await?.user_id;
+        let parse = |id: Option<String>, name: &str| -> RespResult<i64> {
+            id.ok_or_else(|| error::bad_param(name))?
+                .parse()
+                .map_err(|_| error::bad_param(name))
+        };
+        let message_id = match c.detail_type.as_str() {
+            "group" => StoredMessageId::group(&bot, parse(c.group_id, "group_id")?, 0),
+            "private" => StoredMessageId::private(&bot, parse(c.user_id, "user_id")?, 0),
+            "group_temp" => StoredMessageId::group_temp(
+                &bot,
+                parse(c.group_id, "group_id")?,
+                parse(c.user_id, "user_id")?,
+                0,
+            ),
+            ty => return Err(resp_error::unsupported_param(ty)),
+        };
+        let query = HistoryQuery {
+            conversation_key: message_id.conversation_key(),
+            start_time: c.start_time,
+            end_time: c.end_time,
+            start_seq: c.start_seq.map(|s| s as i32),
+            end_seq: c.end_seq.map(|s| s as i32),
+            limit: match c.limit {
+                Some(limit) if limit > 0 => limit as usize,
+                _ => 20,
+            },
+        };
+        Ok(self
+            .database
+            .get_message_history(query)
+            .await?
+            .into_iter()
+            .map(|m| m.event)
+            .collect())
+    }
+
+    async fn search_messages(&self, c: SearchMessages) -> RespResult<Vec<Event>> {
+        if !self.database.not_empty() {
+            return Err(resp_error::database_error("database not enabled"));
+        }
+        if crate::database::index::query_terms(&c.keyword).is_empty() {
+            return Err(error::bad_param("keyword"));
+        }
+        let query = SearchQuery {
+            bot: self.selft().await?.user_id,
+            keyword: c.keyword,
+            group_id: c.group_id,
+            user_id: c.user_id,
+            start_time: c.start_time,
+            end_time: c.end_time,
+            limit: match c.limit {
+                Some(limit) if limit > 0 => limit as usize,
+                _ => 20,
+            },
+        };
+        Ok(self
+            .database
+            .search_messages(query)
+            .await?
+            .into_iter()
+            .map(|m| m.event)
+            .collect())
+    }
+
+    async fn get_forward_message(&self, c: GetForwardMessage) -> RespResult<Vec<MsgSegment>> {
+        crate::parse::forward2node_segs(self.get_client()?, c.id, &self.database).await
+    }
+
     async fn get_self_info(&self) -> RespResult<UserInfo> {
         let name = self
             .get_client()?
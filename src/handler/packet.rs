@@ -0,0 +1,65 @@
+use prost::Message;
+
+use crate::error;
+
+use super::RespResult;
+
+impl super::Handler {
+    /// sends `req` as a `command` uni packet and decodes the response body, for the
+    /// requests ricq has no api for
+    pub(super) async fn uni_request<Rsp: Message + Default>(
+        &self,
+        command: &str,
+        req: impl Message,
+    ) -> RespResult<Rsp> {
+        let cli = self.get_client()?;
+        let packet = cli
+            .engine
+            .read()
+            .await
+            .uni_packet(command, req.encode_to_vec().into());
+        let resp = cli.send_and_wait(packet).await.map_err(error::rq_error)?;
+        Rsp::decode(&*resp.body).map_err(error::rq_error)
+    }
+
+    /// sends `req` as `OidbSvc.0x{command}_{service_type}` and decodes the response body
+    pub(super) async fn oidb<Rsp: Message + Default>(
+        &self,
+        command: u32,
+        service_type: u32,
+        req: impl Message,
+    ) -> RespResult<Rsp> {
+        let pkg = OidbSsoPkg {
+            command,
+            service_type,
+            bodybuffer: req.encode_to_vec(),
+            ..Default::default()
+        };
+        let pkg: OidbSsoPkg = self
+            .uni_request(&format!("OidbSvc.0x{:x}_{}", command, service_type), pkg)
+            .await?;
+        if pkg.result != 0 {
+            return Err(error::rq_error(format!(
+                "oidb 0x{:x} {} {}",
+                command, pkg.result, pkg.error_msg
+            )));
+        }
+        Rsp::decode(&*pkg.bodybuffer).map_err(error::rq_error)
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct OidbSsoPkg {
+    #[prost(uint32, tag = "1")]
+    command: u32,
+    #[prost(uint32, tag = "2")]
+    service_type: u32,
+    #[prost(uint32, tag = "3")]
+    result: u32,
+    #[prost(bytes = "vec", tag = "4")]
+    bodybuffer: Vec<u8>,
+    #[prost(string, tag = "5")]
+    error_msg: String,
+    #[prost(string, tag = "6")]
+    client_version: String,
+}
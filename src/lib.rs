@@ -2,6 +2,7 @@
 
 pub mod config;
 pub mod database;
+mod fetch;
 mod handler;
 mod login;
 pub mod multi;
@@ -19,9 +20,11 @@ const LOG_PATH: &str = "./log";
 pub const DATA_PATH: &str = "./data";
 const IMAGE_DIR: &str = "image";
 const VOICE_DIR: &str = "voice";
+const VIDEO_DIR: &str = "video";
 const FILE_DIR: &str = "file";
 const CLIENT_DIR: &str = "client";
 const CACHE_DIR: &str = "cache";
+const UPLOAD_DIR: &str = "upload";
 
 pub async fn init(data_path: Option<String>, log_path: Option<String>) {
     let data_path = data_path.unwrap_or(DATA_PATH.to_owned());
@@ -33,9 +36,11 @@ pub async fn init(data_path: Option<String>, log_path: Option<String>) {
     tokio::fs::create_dir_all(path!(IMAGE_DIR)).await.ok();
     tokio::fs::create_dir_all(path!(FILE_DIR)).await.ok();
     tokio::fs::create_dir_all(path!(VOICE_DIR)).await.ok();
+    tokio::fs::create_dir_all(path!(VIDEO_DIR)).await.ok();
     tokio::fs::create_dir_all(path!(CLIENT_DIR)).await.ok();
     tokio::fs::remove_dir_all(path!(CACHE_DIR)).await.ok();
     tokio::fs::create_dir_all(path!(CACHE_DIR)).await.ok();
+    tokio::fs::create_dir_all(path!(UPLOAD_DIR)).await.ok();
     tokio::fs::create_dir(log_path.unwrap_or(LOG_PATH.to_owned()))
         .await
         .ok();
@@ -7,6 +7,34 @@ pub struct GetMessage {
     pub message_id: String,
 }
 
+#[derive(Debug, Clone, TryFromValue)]
+pub struct GetMessageHistory {
+    pub detail_type: String,
+    pub group_id: Option<String>,
+    pub user_id: Option<String>,
+    pub start_time: Option<f64>,
+    pub end_time: Option<f64>,
+    pub start_seq: Option<i64>,
+    pub end_seq: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, TryFromValue)]
+pub struct SearchMessages {
+    pub keyword: String,
+    pub group_id: Option<String>,
+    pub user_id: Option<String>,
+    pub start_time: Option<f64>,
+    pub end_time: Option<f64>,
+    pub limit: Option<i64>,
+}
+
+/// `id` of a received forward segment
+#[derive(Debug, Clone, TryFromValue)]
+pub struct GetForwardMessage {
+    pub id: String,
+}
+
 #[derive(Debug, Clone, TryFromValue)]
 pub struct KickGroupMember {
     pub group_id: String,
@@ -81,6 +109,34 @@ pub struct WQUploadFile {
     pub data: Option<OneBotBytes>,
     pub sha256: Option<String>,
     pub file_type: Option<String>,
+    /// mime of a generic file, guessed from `name` if not given
+    pub mime: Option<String>,
+}
+
+/// response of `upload_file` and `upload_file_fragmented`, computed from the stored content
+#[derive(Debug, Clone, PartialEq, Eq, PushToValueMap)]
+pub struct UploadedFile {
+    pub file_id: String,
+    pub sha256: String,
+    pub size: i64,
+}
+
+/// `upload_file_fragmented` with an optional `file_type` at `prepare`
+#[derive(Debug, Clone, TryFromValue)]
+pub struct WQUploadFileFragmented {
+    pub stage: String,
+    pub name: Option<String>,
+    pub total_size: Option<i64>,
+    pub file_type: Option<String>,
+    pub file_id: Option<String>,
+    pub offset: Option<i64>,
+    pub data: Option<OneBotBytes>,
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, TryFromValue)]
+pub struct GetMissingFragments {
+    pub file_id: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, TryFromValue)]
@@ -90,6 +146,50 @@ pub struct WQGetFile {
     pub file_type: Option<String>,
 }
 
+#[derive(Debug, Clone, TryFromValue)]
+pub struct GetGroupFiles {
+    pub group_id: String,
+    /// root folder if not given
+    pub folder_id: Option<String>,
+}
+
+/// a group file, `file_id` and `busid` are from `qq.get_group_files`
+#[derive(Debug, Clone, TryFromValue)]
+pub struct GroupFile {
+    pub group_id: String,
+    pub file_id: String,
+    pub busid: i64,
+    pub folder_id: Option<String>,
+}
+
+/// `file_id` is a file uploaded by `upload_file` with `file_type` file
+#[derive(Debug, Clone, TryFromValue)]
+pub struct UploadGroupFile {
+    pub group_id: String,
+    pub file_id: String,
+    pub folder_id: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, TryFromValue)]
+pub struct CreateGroupFolder {
+    pub group_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, TryFromValue)]
+pub struct RenameGroupFolder {
+    pub group_id: String,
+    pub folder_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, TryFromValue)]
+pub struct GroupFolder {
+    pub group_id: String,
+    pub folder_id: String,
+}
+
 #[derive(Debug, TryFromAction, TryFromValue)]
 pub struct Login {
     pub bot_id: String,
@@ -120,6 +220,9 @@ pub enum WQAction {
     SendMessage(SendMessage),
     DeleteMessage(DeleteMessage),
     GetMessage(GetMessage),
+    GetMessageHistory(GetMessageHistory),
+    SearchMessages(SearchMessages),
+    GetForwardMessage(GetForwardMessage),
 
     GetSelfInfo {},
     GetUserInfo(GetUserInfo),
@@ -132,9 +235,10 @@ pub enum WQAction {
     SetGroupName(SetGroupName),
     LeaveGroup(LeaveGroup),
     UploadFile(WQUploadFile),
-    UploadFileFragmented(UploadFileFragmented),
+    UploadFileFragmented(WQUploadFileFragmented),
     GetFile(WQGetFile),
     GetFileFragmented(GetFileFragmented),
+    GetMissingFragments(GetMissingFragments),
 
     KickGroupMember(KickGroupMember),
     BanGroupMember(BanGroupMember),
@@ -152,6 +256,20 @@ pub enum WQAction {
     GetGroupInviteds {},
 }
 
+pub(crate) const QQ_ACTION_PREFIX: &str = "qq.";
+
+/// `qq.` prefixed actions, parsed with the prefix stripped
+#[derive(Debug, Clone, TryFromAction)]
+pub enum QQAction {
+    GetGroupFiles(GetGroupFiles),
+    GetGroupFileUrl(GroupFile),
+    UploadGroupFile(UploadGroupFile),
+    CreateGroupFolder(CreateGroupFolder),
+    RenameGroupFolder(RenameGroupFolder),
+    DeleteGroupFolder(GroupFolder),
+    DeleteGroupFile(GroupFile),
+}
+
 #[derive(Debug, TryFromAction)]
 pub enum WQMetaAction {
     Login(Login),
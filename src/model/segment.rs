@@ -24,6 +24,13 @@ pub struct Image {
     pub bytes: Option<OneBotBytes>,
 }
 
+#[derive(Debug, Clone, TryFromValue)]
+pub struct Video {
+    pub file_id: String,
+    /// cover image, a blank one is used if not given
+    pub thumb_file_id: Option<String>,
+}
+
 #[derive(Debug, Clone, TryFromValue)]
 pub struct Node {
     pub user_id: String,
@@ -42,5 +49,6 @@ pub enum WQSegment {
     Image(Image),
     Xml(Xml),
     Voice(segment::Voice),
+    Video(Video),
     Node(Node),
 }
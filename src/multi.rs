@@ -1,6 +1,6 @@
 use std::{collections::HashMap, sync::Arc};
 
-use cached::{SizedCache, TimedCache};
+use cached::SizedCache;
 use dashmap::DashMap;
 use once_cell::sync::OnceCell;
 use ricq::{handler::QEvent, RQError};
@@ -19,7 +19,7 @@ use walle_core::{
 };
 
 use crate::{
-    config::QQConfig,
+    config::{FetchConfig, QQConfig},
     database::WQDatabase,
     error::{self, map_action_parse_error},
     handler::Handler,
@@ -34,7 +34,8 @@ pub struct MultiAH {
     pub(crate) data_path: Arc<String>,
     pub(crate) database: Arc<WQDatabase>,
     pub(crate) event_cache: Arc<Mutex<SizedCache<String, Event>>>,
-    pub(crate) file_cache: Arc<Mutex<TimedCache<String, crate::handler::FragmentFile>>>,
+    pub(crate) file_cache: Arc<Mutex<HashMap<String, crate::handler::FragmentFile>>>,
+    pub(crate) fetch: Arc<FetchConfig>,
     pub(crate) unadded_client: DashMap<
         String,
         (
@@ -51,12 +52,14 @@ impl MultiAH {
         event_cache_size: usize,
         database: Arc<WQDatabase>,
         data_path: Arc<String>,
+        fetch: FetchConfig,
     ) -> Self {
         Self {
             super_token,
             data_path,
             event_cache: Arc::new(Mutex::new(SizedCache::with_size(event_cache_size))),
-            file_cache: Arc::new(Mutex::new(TimedCache::with_lifespan(60))),
+            file_cache: Arc::default(),
+            fetch: Arc::new(fetch),
             database,
             ahs: Arc::new(DashMap::default()),
             unadded_client: DashMap::default(),
@@ -141,6 +144,7 @@ impl ActionHandler<Event, Action, Resp> for MultiAH {
                 database: self.database.clone(),
                 uploading_fragment: self.file_cache.clone(),
                 infos: Arc::default(),
+                fetch: self.fetch.clone(),
             };
             match single_handler
                 .start(ob, (id, cs.password, cs.protocol.unwrap_or_default()))
@@ -155,6 +159,7 @@ impl ActionHandler<Event, Action, Resp> for MultiAH {
                 Err(e) => warn!(target: WALLE_Q, "{}", e),
             }
         }
+        crate::handler::spawn_fragment_cleaner(self.data_path.clone(), self.file_cache.clone());
         Ok(vec![])
     }
     async fn call<AH, EH>(&self, action: Action, ob: &Arc<OneBot<AH, EH>>) -> WalleResult<Resp>
@@ -173,6 +178,7 @@ impl ActionHandler<Event, Action, Resp> for MultiAH {
                         database: self.database.clone(),
                         uploading_fragment: self.file_cache.clone(),
                         infos: Arc::default(),
+                        fetch: self.fetch.clone(),
                     };
                     let (net, rx) = ah.init_client(login.bot_id.clone(), login.protocol).await;
                     let cli = ah.get_client().unwrap().clone();
@@ -2,7 +2,7 @@ use super::util::{
     new_event, new_group_audio, new_group_msg, new_group_temp_msg, new_private_audio,
     new_private_msg,
 };
-use crate::database::{Database, SVoice, WQDatabase};
+use crate::database::{RecallState, ReceivedVoice, SVoice, StoredMessageId, WQDatabase};
 use crate::handler::Infos;
 use crate::model::{
     Delete, Disband, FriendPoke, GroupAdminSet, GroupAdminUnset, GroupInvite, GroupMemberBan,
@@ -58,33 +58,63 @@ where
 
         // message
         QEvent::FriendMessage(pme) => {
-            let message = super::msg_chain2msg_seg_vec(pme.inner.elements.clone(), wqdb);
+            let conversation = StoredMessageId::private(&selft.user_id, pme.inner.from_uin, 0);
+            let message =
+                super::msg_chain2msg_seg_vec(pme.inner.elements.clone(), &conversation, wqdb).await;
             new_private_msg(pme.inner, message, selft, wqdb)
         }
         QEvent::GroupMessage(gme) => {
-            let message = super::msg_chain2msg_seg_vec(gme.inner.elements.clone(), wqdb);
+            let conversation = StoredMessageId::group(&selft.user_id, gme.inner.group_code, 0);
+            let message =
+                super::msg_chain2msg_seg_vec(gme.inner.elements.clone(), &conversation, wqdb).await;
             new_group_msg(gme.inner, message, selft, wqdb)
         }
         QEvent::GroupTempMessage(gtme) => {
-            let message = super::msg_chain2msg_seg_vec(gtme.inner.elements.clone(), wqdb);
+            let conversation = StoredMessageId::group_temp(
+                &selft.user_id,
+                gtme.inner.group_code,
+                gtme.inner.from_uin,
+                0,
+            );
+            let message =
+                super::msg_chain2msg_seg_vec(gtme.inner.elements.clone(), &conversation, wqdb)
+                    .await;
             new_group_temp_msg(gtme.inner, message, selft, wqdb)
         }
 
         // notice
         // friend
-        QEvent::FriendMessageRecall(e) => new_event(
-            Some(e.inner.time as f64),
-            (
-                Notice { selft },
-                PrivateMessageDelete {
-                    message_id: e.inner.msg_seq.to_string(),
-                    user_id: e.inner.friend_uin.to_string(),
-                },
-                (),
-                QQ,
-                WalleQ,
-            ),
-        ),
+        QEvent::FriendMessageRecall(e) => {
+            // without database the recalled message has no id to refer to but its seq
+            let message_id = if wqdb.not_empty() {
+                let message_id =
+                    StoredMessageId::private(&selft.user_id, e.inner.friend_uin, e.inner.msg_seq)
+                        .to_string();
+                wqdb.mark_recalled(
+                    message_id.clone(),
+                    RecallState {
+                        time: e.inner.time as f64,
+                        operator_id: e.inner.friend_uin.to_string(),
+                    },
+                );
+                message_id
+            } else {
+                e.inner.msg_seq.to_string()
+            };
+            new_event(
+                Some(e.inner.time as f64),
+                (
+                    Notice { selft },
+                    PrivateMessageDelete {
+                        message_id,
+                        user_id: e.inner.friend_uin.to_string(),
+                    },
+                    (),
+                    QQ,
+                    WalleQ,
+                ),
+            )
+        }
 
         QEvent::NewFriend(e) => new_event(
             None,
@@ -177,13 +207,29 @@ where
             ),
         ),
         QEvent::GroupMessageRecall(e) => {
+            // without database the recalled message has no id to refer to but its seq
+            let message_id = if wqdb.not_empty() {
+                let message_id =
+                    StoredMessageId::group(&selft.user_id, e.inner.group_code, e.inner.msg_seq)
+                        .to_string();
+                wqdb.mark_recalled(
+                    message_id.clone(),
+                    RecallState {
+                        time: e.inner.time as f64,
+                        operator_id: e.inner.operator_uin.to_string(),
+                    },
+                );
+                message_id
+            } else {
+                e.inner.msg_seq.to_string()
+            };
             if e.inner.author_uin == e.inner.operator_uin {
                 new_event(
                     Some(e.inner.time as f64),
                     (
                         Notice { selft },
                         GroupMessageDelete {
-                            message_id: e.inner.msg_seq.to_string(), //todo
+                            message_id,
                             group_id: e.inner.group_code.to_string(),
                             user_id: e.inner.author_uin.to_string(),
                             operator_id: e.inner.operator_uin.to_string(),
@@ -199,7 +245,7 @@ where
                     (
                         Notice { selft },
                         GroupMessageDelete {
-                            message_id: e.inner.msg_seq.to_string(), //todo
+                            message_id,
                             group_id: e.inner.group_code.to_string(),
                             user_id: e.inner.author_uin.to_string(),
                             operator_id: e.inner.operator_uin.to_string(),
@@ -363,7 +409,11 @@ where
                 file_id: gam.inner.audio.0.hex_voice_id(),
             }
             .into()];
-            wqdb.insert_voice(&gam.inner.audio.0);
+            wqdb.insert_voice(&ReceivedVoice {
+                ptt: gam.inner.audio.0.clone(),
+                group_code: Some(gam.inner.group_code),
+                sender: gam.inner.from_uin,
+            });
             let event = new_group_audio(gam.inner, message, selft, wqdb);
             event
         }
@@ -372,7 +422,11 @@ where
                 file_id: fam.inner.audio.0.hex_voice_id(),
             }
             .into()];
-            wqdb.insert_voice(&fam.inner.audio.0);
+            wqdb.insert_voice(&ReceivedVoice {
+                ptt: fam.inner.audio.0.clone(),
+                group_code: None,
+                sender: fam.inner.from_uin,
+            });
             new_private_audio(fam.inner, message, selft, wqdb)
         }
         QEvent::FriendPoke(p) => new_event(
@@ -1,6 +1,6 @@
 use ricq::msg::elem::{self, FlashImage, RQElem};
 use ricq::msg::{MessageChain, MessageElem};
-use ricq::structs::{ForwardMessage, ForwardNode, MessageNode};
+use ricq::structs::{ForwardMessage, ForwardNode, FriendAudio, GroupAudio, MessageNode};
 use ricq::Client;
 use ricq_core::pb::msg::Ptt;
 use tracing::{debug, warn};
@@ -9,12 +9,16 @@ use walle_core::prelude::*;
 use walle_core::resp::RespError;
 use walle_core::segment::{self, Segments};
 
-use crate::database::{Database, Images, SImage, Voices, WQDatabase};
+use crate::config::FetchConfig;
+use crate::database::{
+    Images, ReceivedVoice, SImage, SVoice, StoredMessageId, Videos, Voices, WQDatabase,
+};
 use crate::error;
 use crate::handler::Handler;
 use crate::model::WQSegment;
 
 use super::audio::encode_to_silk;
+use super::util::decode_message_id;
 
 pub struct MsgChainBuilder<'a> {
     pub cli: &'a Client,
@@ -22,8 +26,14 @@ pub struct MsgChainBuilder<'a> {
     pub target: i64,
     pub group: bool,
     data_path: &'a str,
+    fetch: &'a FetchConfig,
     results: RQSends,
     reply: bool,
+    /// group the nodes of a forward are uploaded to, media inside nodes is uploaded there too,
+    /// `None` for friends whose forwards and media are uploaded to the friend
+    forward_group: Option<i64>,
+    /// building the content of a forward node
+    in_forward: bool,
 }
 
 pub enum RQSendItem {
@@ -60,10 +70,13 @@ impl<'a> MsgChainBuilder<'a> {
             cli,
             db: &handler.database,
             data_path: &handler.data_path,
+            fetch: &handler.fetch,
             target,
             group: true,
             results: RQSends::default(),
             reply: false,
+            forward_group: Some(target),
+            in_forward: false,
         }
     }
     pub fn private_chain_builder(cli: &'a Client, handler: &'a Handler, target: i64) -> Self {
@@ -71,10 +84,24 @@ impl<'a> MsgChainBuilder<'a> {
             cli,
             db: &handler.database,
             data_path: &handler.data_path,
+            fetch: &handler.fetch,
             target,
             group: false,
             results: RQSends::default(),
             reply: false,
+            forward_group: None,
+            in_forward: false,
+        }
+    }
+    pub fn group_temp_chain_builder(
+        cli: &'a Client,
+        handler: &'a Handler,
+        group_code: i64,
+        target: i64,
+    ) -> Self {
+        MsgChainBuilder {
+            forward_group: Some(group_code),
+            ..Self::private_chain_builder(cli, handler, target)
         }
     }
     pub(crate) async fn build(mut self, message: Segments) -> Result<RQSendItem, RespError> {
@@ -123,25 +150,9 @@ impl<'a> MsgChainBuilder<'a> {
                 target: 0,
             })),
             WQSegment::Reply(reply) => {
-                let db_event = self
-                    .db
-                    .get_message(&reply.message_id)
-                    .ok_or_else(|| error::message_not_exist(&reply.message_id))?;
-                let event = BaseEvent::<Message>::try_from(db_event.event).unwrap(); //todo check
-                let sub_chain = {
-                    let mut chain = MessageChain::default();
-                    chain.push(elem::Text {
-                        content: event.ty.alt_message,
-                    });
-                    chain
-                };
+                let quote = self.reply_quote(reply).await?;
                 self.reply = true;
-                Ok(self.results.chain.with_reply(elem::Reply {
-                    reply_seq: *db_event.seqs.first().unwrap(),
-                    sender: event.ty.user_id.parse().unwrap(),
-                    time: event.time as i32,
-                    elements: sub_chain,
-                }))
+                Ok(self.results.chain.with_reply(quote))
             }
             WQSegment::Face(face) => {
                 if let Some(id) = face.id {
@@ -161,12 +172,16 @@ impl<'a> MsgChainBuilder<'a> {
             })),
             WQSegment::Image(image) => {
                 let flash = image.flash.unwrap_or_default();
-                if let Some(image) = self.db.get_image::<Images>(
-                    &hex::decode(&image.file_id).map_err(|_| error::bad_param("file_id"))?,
-                )? {
+                if let Some(image) = self
+                    .db
+                    .get_image::<Images>(
+                        &hex::decode(&image.file_id).map_err(|_| error::bad_param("file_id"))?,
+                    )
+                    .await?
+                {
                     self.push_image(image, flash).await
                 } else if let Some(uri) = image.url {
-                    match uri_reader::uget(&uri).await {
+                    match self.fetch.get(&uri, Default::default()).await {
                         Ok(data) => self.push_image_data(data, flash).await,
                         Err(e) => {
                             warn!("uri get failed: {}", e);
@@ -180,11 +195,22 @@ impl<'a> MsgChainBuilder<'a> {
                     Err(error::file_not_found(image.file_id))
                 }
             }
+            // voices can not be carried by forward nodes
+            WQSegment::Voice(voice) if self.in_forward => {
+                let placeholder = self.voice_placeholder(&voice.file_id).await?;
+                Ok(self.results.chain.push(elem::Text::new(placeholder)))
+            }
             WQSegment::Voice(voice) => {
-                match self.db.get_voice(
-                    &hex::decode(&voice.file_id).map_err(|_| error::bad_param("file_id"))?,
-                )? {
-                    Some(Voices::Ptt(ptt)) => Ok(self.results.voice = Some(ptt)),
+                match self
+                    .db
+                    .get_voice(
+                        &hex::decode(&voice.file_id).map_err(|_| error::bad_param("file_id"))?,
+                    )
+                    .await?
+                {
+                    Some(Voices::Ptt(ptt)) | Some(Voices::Received(ReceivedVoice { ptt, .. })) => {
+                        Ok(self.results.voice = Some(ptt))
+                    }
                     Some(Voices::Local(local)) if self.group => {
                         let group_audio = self
                             .cli
@@ -217,15 +243,52 @@ impl<'a> MsgChainBuilder<'a> {
                     }
                 }
             }
+            WQSegment::Video(video) => {
+                let stored = self
+                    .db
+                    .get_video(
+                        &hex::decode(&video.file_id).map_err(|_| error::bad_param("file_id"))?,
+                    )
+                    .await?
+                    .ok_or_else(|| error::file_not_found(&video.file_id))?;
+                match stored {
+                    Videos::Received(received) => {
+                        Ok(self.results.chain.push(elem::VideoFile::from(received)))
+                    }
+                    // ricq only uploads short videos to groups
+                    Videos::Local(_) if !self.group => Err(error::unsupported_param(
+                        "private video not sent to any group yet",
+                    )),
+                    local => {
+                        let data = tokio::fs::read(local.path(self.data_path))
+                            .await
+                            .map_err(|_| error::bad_video_path(local.hex_video_id()))?;
+                        let thumb = self.video_thumb(video.thumb_file_id).await?;
+                        let video_file = self
+                            .cli
+                            .upload_group_short_video(self.target, &data, &thumb)
+                            .await
+                            .map_err(|e| error::rq_error(e))?;
+                        // the uploaded video is sent as is from now on, friends included.
+                        // it has the same md5 and size, so its file_id is kept
+                        self.db
+                            .insert_video(&Videos::Received(video_file.clone().into()));
+                        Ok(self.results.chain.push(video_file))
+                    }
+                }
+            }
             WQSegment::Node(node) => {
                 let sub_builder = MsgChainBuilder {
                     cli: self.cli,
-                    target: self.target,
+                    target: self.forward_group.unwrap_or(self.target),
                     data_path: self.data_path,
-                    group: self.group,
+                    fetch: self.fetch,
+                    group: self.forward_group.is_some(),
                     db: self.db,
                     results: RQSends::default(),
                     reply: false,
+                    forward_group: self.forward_group,
+                    in_forward: true,
                 };
                 let sender_id = node
                     .user_id
@@ -255,21 +318,9 @@ impl<'a> MsgChainBuilder<'a> {
                                 nodes: forwards,
                             })))
                     }
-                    RQSendItem::Voice(_) => {
-                        Ok(self
-                            .results
-                            .forward
-                            .push(ForwardMessage::Message(MessageNode {
-                                sender_id,
-                                sender_name: node.user_name,
-                                time,
-                                elements: {
-                                    let mut chain = MessageChain::default();
-                                    chain.push(ricq::msg::elem::Text::new("[语音]".to_string()));
-                                    chain
-                                },
-                            })))
-                    }
+                    // the sub builder turns voices into `[语音]` text, kept as an error in case
+                    // a voice item ever comes back
+                    RQSendItem::Voice(_) => Err(resp_error::unsupported_segment("voice in node")),
                 }
             }
         }
@@ -306,6 +357,102 @@ impl<'a> MsgChainBuilder<'a> {
             }
         }
     }
+    /// `[语音]` with the download url if the voice was received
+    async fn voice_placeholder(&self, file_id: &str) -> Result<String, RespError> {
+        let voice = self
+            .db
+            .get_voice(&hex::decode(file_id).map_err(|_| error::bad_param("file_id"))?)
+            .await?
+            .ok_or_else(|| error::file_not_found(file_id))?;
+        let Voices::Received(received) = voice else {
+            return Ok("[语音]".to_string());
+        };
+        let url = match received.group_code {
+            Some(group_code) => {
+                self.cli
+                    .get_group_audio_url(group_code, GroupAudio(received.ptt))
+                    .await
+            }
+            None => {
+                self.cli
+                    .get_friend_audio_url(received.sender, FriendAudio(received.ptt))
+                    .await
+            }
+        };
+        match url {
+            Ok(url) => Ok(format!("[语音] {}", url)),
+            Err(e) => {
+                warn!(target: crate::WALLE_Q, "voice url failed: {}", e);
+                Ok("[语音]".to_string())
+            }
+        }
+    }
+    /// quote of the replied message, from the database or decoded from `message_id`
+    async fn reply_quote(&self, reply: segment::Reply) -> Result<elem::Reply, RespError> {
+        if let Some(db_event) = self.db.get_message(&reply.message_id).await? {
+            let event = BaseEvent::<Message>::try_from(db_event.event)
+                .map_err(|_| error::message_not_exist(&reply.message_id))?;
+            return Ok(elem::Reply {
+                reply_seq: *db_event
+                    .seqs
+                    .first()
+                    .ok_or_else(|| error::message_not_exist(&reply.message_id))?,
+                sender: event
+                    .ty
+                    .user_id
+                    .parse()
+                    .map_err(|_| error::bad_param("user_id"))?,
+                time: event.time as i32,
+                elements: quote_chain(&event.ty.message),
+            });
+        }
+        // not stored, the sender has to be given and the quoted content is left empty
+        let sender = reply
+            .user_id
+            .as_deref()
+            .and_then(|user_id| user_id.parse().ok())
+            .ok_or_else(|| error::message_not_exist(&reply.message_id))?;
+        let (reply_seq, time) = match StoredMessageId::parse(&reply.message_id) {
+            Some(id) => (id.seq, 0),
+            None => {
+                let (_, seqs, _, time) = decode_message_id(&reply.message_id)?;
+                let seq = *seqs
+                    .first()
+                    .ok_or_else(|| error::bad_param("message_id decode failed"))?;
+                (seq, time.unwrap_or_default())
+            }
+        };
+        Ok(elem::Reply {
+            reply_seq,
+            sender,
+            time,
+            elements: MessageChain::default(),
+        })
+    }
+    /// cover of an uploaded video, from a stored image or a blank one
+    async fn video_thumb(&self, thumb_file_id: Option<String>) -> Result<Vec<u8>, RespError> {
+        let Some(file_id) = thumb_file_id else {
+            let mut data = vec![];
+            image::DynamicImage::new_rgb8(320, 180)
+                .write_to(
+                    &mut std::io::Cursor::new(&mut data),
+                    image::ImageOutputFormat::Jpeg(80),
+                )
+                .map_err(|e| error::bad_image_data(e))?;
+            return Ok(data);
+        };
+        let image = self
+            .db
+            .get_image::<Images>(
+                &hex::decode(&file_id).map_err(|_| error::bad_param("thumb_file_id"))?,
+            )
+            .await?
+            .ok_or_else(|| error::file_not_found(&file_id))?;
+        image
+            .data(self.data_path)
+            .await
+            .map_err(|e| error::bad_image_data(e))
+    }
     pub(crate) async fn push_image_data(
         &mut self,
         data: Vec<u8>,
@@ -422,6 +569,18 @@ pub(crate) fn rq_elem2msg_seg(elem: RQElem, wqdb: &WQDatabase) -> Option<MsgSegm
                 })
             }
         },
+        RQElem::VideoFile(video) => {
+            let video = Videos::Received(video.into());
+            wqdb.insert_video(&video);
+            Some(MsgSegment {
+                ty: "video".to_string(),
+                data: value_map! {"file_id": video.hex_video_id()},
+            })
+        }
+        RQElem::RichMsg(rich) if let Some(res_id) = forward_res_id(&rich) => Some(MsgSegment {
+            ty: "forward".to_string(),
+            data: value_map! {"id": res_id},
+        }),
         RQElem::RichMsg(rich) => Some(MsgSegment {
             ty: "xml".to_string(),
             data: value_map! {
@@ -440,16 +599,213 @@ pub(crate) fn rq_elem2msg_seg(elem: RQElem, wqdb: &WQDatabase) -> Option<MsgSegm
     }
 }
 
-pub(crate) fn msg_chain2msg_seg_vec(chain: MessageChain, wqdb: &WQDatabase) -> Vec<MsgSegment> {
+/// res_id of a merged forward, which is received as a service 35 xml
+fn forward_res_id(rich: &elem::RichMsg) -> Option<String> {
+    const RES_ID: &str = "m_resid=\"";
+    if rich.service_id != 35 {
+        return None;
+    }
+    let start = rich.template1.find(RES_ID)? + RES_ID.len();
+    let len = rich.template1[start..].find('"')?;
+    Some(rich.template1[start..start + len].to_owned()).filter(|id| !id.is_empty())
+}
+
+/// upload `forwards` within the group `group_code` and reference them with a service 35 xml,
+/// which is how merged forwards are sent to group_temp sessions
+pub(crate) async fn upload_forward(
+    cli: &Client,
+    group_code: i64,
+    forwards: Vec<ForwardMessage>,
+) -> Result<MessageChain, RespError> {
+    let (count, preview) = (forwards.len(), forward_preview(&forwards));
+    let res_id = cli
+        .upload_msgs(group_code, forwards, false)
+        .await
+        .map_err(error::rq_error)?;
+    Ok(forward_chain(
+        &res_id,
+        &walle_core::util::new_uuid(),
+        count,
+        &preview,
+    ))
+}
+
+/// titles of the first nodes shown on the forward card
+pub(crate) fn forward_preview(forwards: &[ForwardMessage]) -> String {
+    forwards
+        .iter()
+        .take(4)
+        .map(|forward| {
+            let (name, content) = match forward {
+                ForwardMessage::Message(node) => (&node.sender_name, node.elements.to_string()),
+                ForwardMessage::Forward(node) => (&node.sender_name, "[聊天记录]".to_string()),
+            };
+            format!(
+                r#"<title size="26" color="#777777" maxLines="2" lineSpace="12">{}: {}</title>"#,
+                xml_escape(name),
+                xml_escape(&content)
+            )
+        })
+        .collect()
+}
+
+/// service 35 card of a merged forward, `res_id` is empty for forwards nested in the upload
+/// of `file_name`
+pub(crate) fn forward_chain(
+    res_id: &str,
+    file_name: &str,
+    count: usize,
+    preview: &str,
+) -> MessageChain {
+    let template1 = format!(
+        concat!(
+            r#"<?xml version='1.0' encoding='UTF-8' standalone='yes' ?>"#,
+            r#"<msg serviceID="35" templateID="1" action="viewMultiMsg" brief="[聊天记录]" "#,
+            r#"m_resid="{res_id}" m_fileName="{file_name}" tSum="{count}" sourceMsgId="0" "#,
+            r#"url="" flag="3" adverSign="0" multiMsgFlag="0">"#,
+            r#"<item layout="1" advertiser_id="0" aid="0">"#,
+            r#"<title size="34" maxLines="2" lineSpace="12">聊天记录</title>{preview}"#,
+            r#"<hr hidden="false" style="0" />"#,
+            r#"<summary size="26" color="#777777">查看{count}条转发消息</summary></item>"#,
+            r#"<source name="聊天记录" icon="" action="" appid="-1" /></msg>"#,
+        ),
+        res_id = res_id,
+        file_name = file_name,
+        count = count,
+        preview = preview,
+    );
+    let mut chain = MessageChain::default();
+    chain.push(elem::RichMsg {
+        service_id: 35,
+        template1,
+    });
+    chain
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// download a merged forward as node segments, nested forwards are expanded as well
+pub(crate) async fn forward2node_segs(
+    cli: &Client,
+    res_id: String,
+    wqdb: &WQDatabase,
+) -> Result<Vec<MsgSegment>, RespError> {
+    let forwards = cli.download_msgs(res_id).await.map_err(error::rq_error)?;
+    forward_nodes(cli, forwards, wqdb).await
+}
+
+#[async_recursion::async_recursion]
+async fn forward_nodes(
+    cli: &Client,
+    forwards: Vec<ForwardMessage>,
+    wqdb: &WQDatabase,
+) -> Result<Vec<MsgSegment>, RespError> {
+    let mut nodes = vec![];
+    for forward in forwards {
+        let (sender_id, sender_name, time, message) = match forward {
+            ForwardMessage::Message(node) => {
+                let mut message = vec![];
+                for elem in node.elements {
+                    match elem {
+                        RQElem::RichMsg(rich) if let Some(res_id) = forward_res_id(&rich) => {
+                            message.extend(forward2node_segs(cli, res_id, wqdb).await?)
+                        }
+                        elem => message.extend(rq_elem2msg_seg(elem, wqdb)),
+                    }
+                }
+                (node.sender_id, node.sender_name, node.time, message)
+            }
+            ForwardMessage::Forward(node) => (
+                node.sender_id,
+                node.sender_name,
+                node.time,
+                forward_nodes(cli, node.nodes, wqdb).await?,
+            ),
+        };
+        // same shape as the node segments accepted on send, time in milliseconds
+        nodes.push(MsgSegment {
+            ty: "node".to_string(),
+            data: value_map! {
+                "user_id": sender_id.to_string(),
+                "time": time as f64 * 1000.0,
+                "user_name": sender_name,
+                "message": message
+            },
+        });
+    }
+    Ok(nodes)
+}
+
+/// reply segment with the same message_id as the replied message, the quoted text is
+/// kept as `alt_message` if that message is not stored
+async fn reply2msg_seg(
+    reply: elem::Reply,
+    conversation: &StoredMessageId,
+    wqdb: &WQDatabase,
+) -> MsgSegment {
+    let mut data = value_map! {"user_id": reply.sender.to_string()};
+    if wqdb.not_empty() {
+        let message_id = StoredMessageId {
+            seq: reply.reply_seq,
+            ..conversation.clone()
+        }
+        .to_string();
+        let stored = matches!(wqdb.get_message(&message_id).await, Ok(Some(_)));
+        if !stored {
+            data.insert("alt_message".to_owned(), reply.elements.to_string().into());
+        }
+        data.insert("message_id".to_owned(), message_id.into());
+    } else {
+        // rand of the replied message is not carried by the quote, an id built without it
+        // would never match the original message
+        data.insert("alt_message".to_owned(), reply.elements.to_string().into());
+    }
+    MsgSegment {
+        ty: "reply".to_owned(),
+        data,
+    }
+}
+
+/// elements quoted in a reply, segments other than text and mentions are kept as alt text
+fn quote_chain(message: &Segments) -> MessageChain {
+    let mut chain = MessageChain::default();
+    for seg in message {
+        match seg.ty.as_str() {
+            "text" => chain.push(elem::Text {
+                content: seg.data.get_downcast("text").unwrap_or_default(),
+            }),
+            "mention" if let Ok(target) = seg.data.get_downcast::<String>("user_id") => {
+                chain.push(elem::At {
+                    display: format!("@{}", target),
+                    target: target.parse().unwrap_or_default(),
+                })
+            }
+            "mention_all" => chain.push(elem::At {
+                display: "@全体成员".to_string(),
+                target: 0,
+            }),
+            _ => chain.push(elem::Text { content: seg.alt() }),
+        }
+    }
+    chain
+}
+
+/// `conversation` is the id of any message in the conversation `chain` is received from,
+/// its seq is ignored
+pub(crate) async fn msg_chain2msg_seg_vec(
+    chain: MessageChain,
+    conversation: &StoredMessageId,
+    wqdb: &WQDatabase,
+) -> Vec<MsgSegment> {
     let mut rv = vec![];
     if let Some(reply) = chain.reply() {
-        rv.push(
-            segment::Reply {
-                message_id: reply.reply_seq.to_string(),
-                user_id: Some(reply.sender.to_string()),
-            }
-            .into(),
-        )
+        rv.push(reply2msg_seg(reply, conversation, wqdb).await)
     }
     for seg in chain.into_iter().filter_map(|s| rq_elem2msg_seg(s, wqdb)) {
         rv.push(seg);
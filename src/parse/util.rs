@@ -19,7 +19,7 @@ use walle_core::{
 };
 
 use crate::{
-    database::{Database, WQDatabase},
+    database::{StoredMessageId, WQDatabase},
     error,
     model::{GroupTemp, Names, UserName, WalleQ, QQ},
 };
@@ -130,20 +130,26 @@ pub(crate) fn new_group_msg(
     selft: Selft,
     database: &WQDatabase,
 ) -> Event {
+    let message_id = if database.not_empty() {
+        StoredMessageId::group(
+            &selft.user_id,
+            group_message.group_code,
+            *group_message.seqs.first().unwrap(),
+        )
+        .to_string()
+    } else {
+        new_group_message_id(
+            group_message.group_code,
+            group_message.seqs.clone(),
+            group_message.rands.clone(),
+        )
+    };
     let event = new_event(
         Some(group_message.time as f64),
         (
             Message {
                 selft,
-                message_id: if database.not_empty() {
-                    group_message.seqs.first().unwrap().to_string()
-                } else {
-                    new_group_message_id(
-                        group_message.group_code,
-                        group_message.seqs.clone(),
-                        group_message.rands.clone(),
-                    )
-                },
+                message_id,
                 alt_message: alt_message(&message),
                 message,
                 user_id: group_message.from_uin.to_string(),
@@ -171,16 +177,18 @@ pub(crate) async fn new_group_receipt(
     selft: Selft,
     database: &WQDatabase,
 ) -> Event {
+    let message_id = if database.not_empty() {
+        StoredMessageId::group(&selft.user_id, group_code, *receipt.seqs.first().unwrap())
+            .to_string()
+    } else {
+        new_group_message_id(group_code, receipt.seqs.clone(), receipt.rands.clone())
+    };
     let event = new_event(
         Some(receipt.time as f64),
         (
             Message {
                 selft,
-                message_id: if database.not_empty() {
-                    receipt.seqs.first().unwrap().to_string()
-                } else {
-                    new_group_message_id(group_code, receipt.seqs.clone(), receipt.rands.clone())
-                },
+                message_id,
                 alt_message: alt_message(&message),
                 message,
                 user_id: cli.uin().await.to_string(),
@@ -203,20 +211,26 @@ pub(crate) fn new_group_audio(
     selft: Selft,
     database: &WQDatabase,
 ) -> Event {
+    let message_id = if database.not_empty() {
+        StoredMessageId::group(
+            &selft.user_id,
+            group_audio.group_code,
+            *group_audio.seqs.first().unwrap(),
+        )
+        .to_string()
+    } else {
+        new_group_message_id(
+            group_audio.group_code,
+            group_audio.seqs.clone(),
+            group_audio.rands.clone(),
+        )
+    };
     let event = new_event(
         Some(group_audio.time as f64),
         (
             Message {
                 selft,
-                message_id: if database.not_empty() {
-                    group_audio.seqs.first().unwrap().to_string()
-                } else {
-                    new_group_message_id(
-                        group_audio.group_code,
-                        group_audio.seqs.clone(),
-                        group_audio.rands.clone(),
-                    )
-                },
+                message_id,
                 alt_message: alt_message(&message),
                 message,
                 user_id: group_audio.from_uin.to_string(),
@@ -242,6 +256,21 @@ pub(crate) fn new_private_msg(
     selft: Selft,
     database: &WQDatabase,
 ) -> Event {
+    let message_id = if database.not_empty() {
+        StoredMessageId::private(
+            &selft.user_id,
+            friend_message.from_uin,
+            *friend_message.seqs.first().unwrap(),
+        )
+        .to_string()
+    } else {
+        new_private_message_id(
+            friend_message.from_uin,
+            friend_message.time,
+            friend_message.seqs.clone(),
+            friend_message.rands.clone(),
+        )
+    };
     let event = new_event(
         Some(friend_message.time as f64),
         (
@@ -249,16 +278,7 @@ pub(crate) fn new_private_msg(
                 selft,
                 alt_message: alt_message(&message),
                 message,
-                message_id: if database.not_empty() {
-                    friend_message.seqs.first().unwrap().to_string()
-                } else {
-                    new_private_message_id(
-                        friend_message.from_uin,
-                        friend_message.time,
-                        friend_message.seqs.clone(),
-                        friend_message.rands.clone(),
-                    )
-                },
+                message_id,
                 user_id: friend_message.from_uin.to_string(),
             },
             Private {},
@@ -281,6 +301,17 @@ pub(crate) async fn new_private_receipt(
     selft: Selft,
     database: &WQDatabase,
 ) -> Event {
+    let message_id = if database.not_empty() {
+        StoredMessageId::private(&selft.user_id, target_id, *receipt.seqs.first().unwrap())
+            .to_string()
+    } else {
+        new_private_message_id(
+            target_id,
+            receipt.time as i32,
+            receipt.seqs.clone(),
+            receipt.rands.clone(),
+        )
+    };
     let event = new_event(
         Some(receipt.time as f64),
         (
@@ -288,16 +319,7 @@ pub(crate) async fn new_private_receipt(
                 selft,
                 alt_message: alt_message(&message),
                 message,
-                message_id: if database.not_empty() {
-                    receipt.seqs.first().unwrap().to_string()
-                } else {
-                    new_private_message_id(
-                        target_id,
-                        receipt.time as i32,
-                        receipt.seqs.clone(),
-                        receipt.rands.clone(),
-                    )
-                },
+                message_id,
                 user_id: cli.uin().await.to_string(),
             },
             Private {},
@@ -318,6 +340,21 @@ pub(crate) fn new_private_audio(
     selft: Selft,
     database: &WQDatabase,
 ) -> Event {
+    let message_id = if database.not_empty() {
+        StoredMessageId::private(
+            &selft.user_id,
+            friend_audio.from_uin,
+            *friend_audio.seqs.first().unwrap(),
+        )
+        .to_string()
+    } else {
+        new_private_message_id(
+            friend_audio.from_uin,
+            friend_audio.time,
+            friend_audio.seqs.clone(),
+            friend_audio.rands.clone(),
+        )
+    };
     let event = new_event(
         Some(friend_audio.time as f64),
         (
@@ -325,16 +362,7 @@ pub(crate) fn new_private_audio(
                 selft,
                 alt_message: alt_message(&message),
                 message,
-                message_id: if database.not_empty() {
-                    friend_audio.seqs.first().unwrap().to_string()
-                } else {
-                    new_private_message_id(
-                        friend_audio.from_uin,
-                        friend_audio.time,
-                        friend_audio.seqs.clone(),
-                        friend_audio.rands.clone(),
-                    )
-                },
+                message_id,
                 user_id: friend_audio.from_uin.to_string(),
             },
             Private {},
@@ -355,6 +383,22 @@ pub(crate) fn new_group_temp_msg(
     selft: Selft,
     database: &WQDatabase,
 ) -> Event {
+    let message_id = if database.not_empty() {
+        StoredMessageId::group_temp(
+            &selft.user_id,
+            group_temp.group_code,
+            group_temp.from_uin,
+            *group_temp.seqs.first().unwrap(),
+        )
+        .to_string()
+    } else {
+        new_private_message_id(
+            group_temp.from_uin,
+            group_temp.time,
+            group_temp.seqs.clone(),
+            group_temp.rands.clone(),
+        )
+    };
     let event = new_event(
         Some(group_temp.time as f64),
         (
@@ -362,16 +406,7 @@ pub(crate) fn new_group_temp_msg(
                 selft,
                 alt_message: alt_message(&message),
                 message,
-                message_id: if database.not_empty() {
-                    group_temp.seqs.first().unwrap().to_string()
-                } else {
-                    new_private_message_id(
-                        group_temp.from_uin,
-                        group_temp.time,
-                        group_temp.seqs.clone(),
-                        group_temp.rands.clone(),
-                    )
-                },
+                message_id,
                 user_id: group_temp.from_uin.to_string(),
             },
             GroupTemp {
@@ -397,6 +432,22 @@ pub(crate) async fn new_group_temp_receipt(
     selft: Selft,
     database: &WQDatabase,
 ) -> Event {
+    let message_id = if database.not_empty() {
+        StoredMessageId::group_temp(
+            &selft.user_id,
+            group_code,
+            target_id,
+            *receipt.seqs.first().unwrap(),
+        )
+        .to_string()
+    } else {
+        new_private_message_id(
+            target_id,
+            receipt.time as i32,
+            receipt.seqs.clone(),
+            receipt.rands.clone(),
+        )
+    };
     let event = new_event(
         Some(receipt.time as f64),
         (
@@ -404,16 +455,7 @@ pub(crate) async fn new_group_temp_receipt(
                 selft,
                 alt_message: alt_message(&message),
                 message,
-                message_id: if database.not_empty() {
-                    receipt.seqs.first().unwrap().to_string()
-                } else {
-                    new_private_message_id(
-                        target_id,
-                        receipt.time as i32,
-                        receipt.seqs.clone(),
-                        receipt.rands.clone(),
-                    )
-                },
+                message_id,
                 user_id: cli.uin().await.to_string(),
             },
             GroupTemp {